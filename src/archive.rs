@@ -0,0 +1,462 @@
+//! The tar, tar.gz, and zip files of `download --archive`
+
+use crate::*;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// What `download --archive` writes, by the extension of its path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// `-` is a plain tar, to be compressed by whatever reads it
+    pub(crate) fn of(path: &std::path::Path) -> Option<ArchiveFormat> {
+        let name = path.to_string_lossy().to_lowercase();
+        if name == "-" || name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes files into a tar or zip archive as they come, without keeping their content
+///
+/// Only the central directory of a zip, a few bytes per file, is kept until `finish`.
+pub(crate) struct ArchiveWriter {
+    pub(crate) path: PathBuf,
+    format: ArchiveFormat,
+    out: Box<dyn Write>,
+    /// Compresses the tar of a `.tar.gz` into `out`, if this is one
+    gzip: Option<GzipEncoder<Box<dyn Write>>>,
+    /// The zip's central directory records of the files so far
+    central_directory: Vec<u8>,
+    files: usize,
+    /// How many bytes were written, which is where the next file of a zip starts
+    offset: u64,
+}
+
+impl ArchiveWriter {
+    pub(crate) fn create(
+        path: &std::path::Path,
+        format: ArchiveFormat,
+    ) -> std::io::Result<ArchiveWriter> {
+        let mut out: Box<dyn Write> = if path.as_os_str() == "-" {
+            Box::new(std::io::BufWriter::new(std::io::stdout()))
+        } else {
+            Box::new(std::io::BufWriter::new(File::create(path)?))
+        };
+        let gzip = match format {
+            ArchiveFormat::TarGz => Some(GzipEncoder::new(std::mem::replace(
+                &mut out,
+                Box::new(std::io::sink()),
+            ))),
+            _ => None,
+        };
+        Ok(ArchiveWriter {
+            path: path.to_path_buf(),
+            format,
+            out,
+            gzip,
+            central_directory: vec![],
+            files: 0,
+            offset: 0,
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match &mut self.gzip {
+            Some(gzip) => gzip.write_all(bytes)?,
+            None => self.out.write_all(bytes)?,
+        }
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Adds a file, `name` being its path inside the archive with `/` between directories
+    pub(crate) fn add(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        modified: Timestamp,
+    ) -> std::io::Result<()> {
+        match self.format {
+            ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+                // longer names don't fit into the header, they go into a pax header before it
+                if name.len() > 100 {
+                    let header = pax_record("path", name);
+                    self.add_tar_entry("././@PaxHeader", b'x', header.as_bytes(), modified)?;
+                }
+                self.add_tar_entry(name, b'0', content, modified)
+            }
+            ArchiveFormat::Zip => self.add_zip_entry(name, content, modified),
+        }
+    }
+
+    fn add_tar_entry(
+        &mut self,
+        name: &str,
+        kind: u8,
+        content: &[u8],
+        modified: Timestamp,
+    ) -> std::io::Result<()> {
+        let mut header = [0u8; 512];
+        let mut name_end = name.len().min(100);
+        while !name.is_char_boundary(name_end) {
+            name_end -= 1;
+        }
+        header[..name_end].copy_from_slice(&name.as_bytes()[..name_end]);
+        let octal = |field: &mut [u8], value: u128| {
+            let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+            field.copy_from_slice(&digits.as_bytes()[digits.len() - field.len()..]);
+        };
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], content.len() as u128);
+        octal(&mut header[136..148], modified.seconds());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        // the checksum is calculated with spaces in its own place
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.write(&header)?;
+        self.write(content)?;
+        let padding = (512 - content.len() % 512) % 512;
+        self.write(&[0; 512][..padding])
+    }
+
+    fn add_zip_entry(
+        &mut self,
+        name: &str,
+        content: &[u8],
+        modified: Timestamp,
+    ) -> std::io::Result<()> {
+        let offset = self.offset;
+        if self.files >= 0xFFFF || offset + content.len() as u64 + 1024 > u32::MAX as u64 {
+            return Err(std::io::Error::other(
+                "Zip archives can have at most 65535 files and 4 GiB, use .tar.gz instead",
+            ));
+        }
+        let (time, date) = dos_date_time(modified);
+        let crc = crc32(content);
+        let size = content.len() as u32;
+        // the fields both headers share, from the version needed to extract to the name length
+        let mut shared = vec![];
+        shared.extend_from_slice(&20u16.to_le_bytes());
+        // the name is UTF-8, and the content stored as is
+        shared.extend_from_slice(&0x0800u16.to_le_bytes());
+        shared.extend_from_slice(&0u16.to_le_bytes());
+        shared.extend_from_slice(&time.to_le_bytes());
+        shared.extend_from_slice(&date.to_le_bytes());
+        shared.extend_from_slice(&crc.to_le_bytes());
+        shared.extend_from_slice(&size.to_le_bytes());
+        shared.extend_from_slice(&size.to_le_bytes());
+        shared.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+        let mut local = 0x0403_4b50u32.to_le_bytes().to_vec();
+        local.extend_from_slice(&shared);
+        local.extend_from_slice(&0u16.to_le_bytes());
+        local.extend_from_slice(name.as_bytes());
+        self.write(&local)?;
+        self.write(content)?;
+
+        let central = &mut self.central_directory;
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&shared);
+        // no extra field, comment, or attributes, and on the first disk
+        central.extend_from_slice(&[0; 12]);
+        central.extend_from_slice(&(offset as u32).to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+        self.files += 1;
+        Ok(())
+    }
+
+    /// Ends the archive, which isn't readable before
+    pub(crate) fn finish(mut self) -> std::io::Result<()> {
+        match self.format {
+            ArchiveFormat::Tar | ArchiveFormat::TarGz => self.write(&[0; 1024])?,
+            ArchiveFormat::Zip => {
+                let start = self.offset as u32;
+                let central_directory = std::mem::take(&mut self.central_directory);
+                self.write(&central_directory)?;
+                let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+                end.extend_from_slice(&[0; 4]);
+                end.extend_from_slice(&(self.files as u16).to_le_bytes());
+                end.extend_from_slice(&(self.files as u16).to_le_bytes());
+                end.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+                end.extend_from_slice(&start.to_le_bytes());
+                end.extend_from_slice(&0u16.to_le_bytes());
+                self.write(&end)?;
+            }
+        }
+        if let Some(gzip) = self.gzip.take() {
+            self.out = gzip.finish()?;
+        }
+        self.out.flush()
+    }
+}
+
+/// A pax extended header record, which starts with its own length
+fn pax_record(key: &str, value: &str) -> String {
+    let record = format!(" {}={}\n", key, value);
+    let mut length = record.len();
+    while length != record.len() + length.to_string().len() {
+        length = record.len() + length.to_string().len();
+    }
+    format!("{}{}", length, record)
+}
+
+/// The MS-DOS `(time, date)` zip stores, 1980-01-01 for anything before
+fn dos_date_time(time: Timestamp) -> (u16, u16) {
+    let (year, month, day) = time.date();
+    if year < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    let seconds = (time.seconds() % 86400) as u32;
+    let dos_time = (seconds / 3600) << 11 | (seconds / 60 % 60) << 5 | (seconds % 60 / 2);
+    let dos_date = ((year - 1980).min(127) as u32) << 9 | month << 5 | day;
+    (dos_time as u16, dos_date as u16)
+}
+
+/// The path of a file inside an archive, with `/` between directories on every platform
+pub(crate) fn archive_name(path: &std::path::Path) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use std::convert::TryInto;
+
+    /// The name, mtime, and content of each file in a tar, checking the header checksums
+    fn read_tar(tar: &[u8]) -> Vec<(String, u128, Vec<u8>)> {
+        let octal = |field: &[u8]| {
+            let digits = String::from_utf8_lossy(field);
+            u128::from_str_radix(digits.trim_matches(['\0', ' ']), 8).unwrap()
+        };
+        let mut files = vec![];
+        let mut long_name = None;
+        let mut offset = 0;
+        while tar[offset..offset + 512].iter().any(|&b| b != 0) {
+            let header = &tar[offset..offset + 512];
+            let mut blank = header.to_vec();
+            blank[148..156].copy_from_slice(b"        ");
+            let sum: u32 = blank.iter().map(|&b| b as u32).sum();
+            assert_eq!(octal(&header[148..156]), sum as u128);
+            assert_eq!(&header[257..263], b"ustar\0");
+
+            let size = octal(&header[124..136]) as usize;
+            let content = tar[offset + 512..offset + 512 + size].to_vec();
+            offset += 512 + size.div_ceil(512) * 512;
+            let name = String::from_utf8_lossy(&header[..100])
+                .trim_end_matches('\0')
+                .to_string();
+            if header[156] == b'x' {
+                let record = String::from_utf8(content).unwrap();
+                long_name = record
+                    .split_once(" path=")
+                    .map(|(_, p)| p.trim_end().to_string());
+                continue;
+            }
+            files.push((
+                long_name.take().unwrap_or(name),
+                octal(&header[136..148]),
+                content,
+            ));
+        }
+        assert_eq!(tar.len(), offset + 1024);
+        files
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(ArchiveFormat::of("-".as_ref()), Some(ArchiveFormat::Tar));
+        assert_eq!(
+            ArchiveFormat::of("emoji.TAR.gz".as_ref()),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::of("a.tgz".as_ref()),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::of("a.zip".as_ref()),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::of("a.rar".as_ref()), None);
+
+        assert_eq!(pax_record("path", "a"), "9 path=a\n");
+        assert_eq!(
+            pax_record("path", &"a".repeat(91)),
+            "101 path=".to_string() + &"a".repeat(91) + "\n"
+        );
+        // 2024-06-01T11:59:59Z
+        assert_eq!(
+            dos_date_time(Timestamp(1717243199)),
+            (11 << 11 | 59 << 5 | 29, 44 << 9 | 6 << 5 | 1)
+        );
+        assert_eq!(dos_date_time(Timestamp(0)), (0, 1 << 5 | 1));
+    }
+
+    #[test]
+    fn tar() {
+        let dir = TestDir::new("tar-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("emoji.tar");
+        let long = format!("{}/{}.png", "user".repeat(20), "parrot".repeat(10));
+        let mut archive = ArchiveWriter::create(&path, ArchiveFormat::Tar).unwrap();
+        archive
+            .add("a.json", b"{}\n", Timestamp(1717243199))
+            .unwrap();
+        archive.add(&long, &[7; 513], Timestamp(5)).unwrap();
+        archive.finish().unwrap();
+
+        let files = read_tar(&std::fs::read(&path).unwrap());
+        assert_eq!(
+            files,
+            vec![
+                ("a.json".to_string(), 1717243199, b"{}\n".to_vec()),
+                (long, 5, vec![7; 513]),
+            ]
+        );
+    }
+
+    #[test]
+    fn tar_gz() {
+        let dir = TestDir::new("tar-gz-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("emoji.tgz");
+        let mut archive = ArchiveWriter::create(&path, ArchiveFormat::TarGz).unwrap();
+        archive.add("a.json", b"{}\n", Timestamp(5)).unwrap();
+        archive.finish().unwrap();
+
+        let tar = decompress_metadata(std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            read_tar(&tar),
+            vec![("a.json".to_string(), 5, b"{}\n".to_vec())]
+        );
+    }
+
+    #[test]
+    fn zip() {
+        let dir = TestDir::new("zip-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("emoji.zip");
+        let mut archive = ArchiveWriter::create(&path, ArchiveFormat::Zip).unwrap();
+        archive
+            .add("a.txt", b"hello", Timestamp(1717243199))
+            .unwrap();
+        archive.add("b/ü.txt", b"", Timestamp(0)).unwrap();
+        archive.finish().unwrap();
+
+        let zip = std::fs::read(&path).unwrap();
+        let u16_at = |at: usize| u16::from_le_bytes([zip[at], zip[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), 0x0403_4b50);
+        assert_eq!(u32_at(14), 0x3610_a686);
+        assert_eq!(&zip[30..35], b"a.txt");
+        assert_eq!(&zip[35..40], b"hello");
+
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        assert_eq!(u16_at(end + 10), 2);
+        let central = u32_at(end + 16) as usize;
+        assert_eq!(u32_at(central), 0x0201_4b50);
+        assert_eq!(u32_at(central + 42), 0);
+        let second = central + 46 + 5;
+        assert_eq!(u32_at(second), 0x0201_4b50);
+        // right after the first file
+        assert_eq!(u32_at(second + 42), 40);
+        assert_eq!(&zip[second + 46..second + 46 + 8], "b/ü.txt".as_bytes());
+    }
+
+    #[test]
+    fn downloads_into_a_tar() {
+        let dir = TestDir::new("archive-download-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 200,
+                headers: vec![("Content-Type", "image/gif".into())],
+                body: b"GIF89a".to_vec(),
+            },
+            MockResponse::status(404),
+        ]);
+        let mut parrot = Emoji::new("parrot");
+        parrot.url = format!("{}/parrot", server.url);
+        parrot.user_display_name = "Robin".into();
+        parrot.created = Timestamp(1717243199);
+        let mut gone = Emoji::new("gone");
+        gone.url = format!("{}/gone.png", server.url);
+        gone.user_display_name = "Robin".into();
+        gone.created = Timestamp(1600000000);
+        let mut alias = Emoji::new("party");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+
+        let path = dir.path.join("emoji.tar");
+        let settings = DownloadSettings {
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let archive = open_archive(&path, &[alias], &settings).unwrap();
+        let client = RetryingClient::new(Client::new(), 0);
+        let summary = download_into_archive(
+            &client,
+            archive,
+            &[&parrot, &gone],
+            Layout::ByUser,
+            &settings,
+            &GlobalOptions::default(),
+            ProgressMode::None,
+        )
+        .unwrap();
+        assert_eq!(summary.failed, 1);
+
+        let files = read_tar(&std::fs::read(&path).unwrap());
+        let names: Vec<&str> = files.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "aliases.json",
+                "Robin/parrot.gif",
+                "Robin/parrot.json",
+                "Robin/gone.json"
+            ]
+        );
+        assert_eq!(files[1].1, 1717243199);
+        assert_eq!(files[1].2, b"GIF89a");
+        let json: Emoji = serde_json::from_slice(&files[2].2).unwrap();
+        assert_eq!(json.image_bytes, Some(6));
+        assert!(String::from_utf8_lossy(&files[0].2).contains("\"party\": \"parrot\""));
+
+        // existing archives are only replaced with --force
+        assert!(open_archive(&path, &[], &settings).is_err());
+        let forced = DownloadSettings {
+            force: true,
+            ..DownloadSettings::default()
+        };
+        assert!(open_archive(&path, &[], &forced).is_ok());
+        assert!(open_archive(&dir.path.join("emoji.7z"), &[], &forced).is_err());
+    }
+}
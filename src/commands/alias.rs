@@ -0,0 +1,134 @@
+//! `alias`
+
+use crate::*;
+
+/// An `(alias, target)` pair or why the line couldn't be parsed
+type AliasPair = Result<(String, String), String>;
+
+/// Parses lines of `alias target` pairs, keeping the line numbers for error messages
+fn parse_alias_pairs(content: &str) -> Vec<(usize, AliasPair)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line_no, line)| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            match columns.as_slice() {
+                [alias, target] => (line_no, Ok((alias.to_string(), target.to_string()))),
+                _ => (
+                    line_no,
+                    Err(format!("expected 2 columns, found {}", columns.len())),
+                ),
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn alias(
+    client: &RetryingClient,
+    alias_opts: AliasOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = alias_opts.global + global_opts;
+    let slack = alias_opts.slack;
+
+    let mut pairs = vec![];
+    if let (Some(alias_name), Some(target_name)) = (alias_opts.alias_name, alias_opts.target_name) {
+        pairs.push((0, Ok((alias_name, target_name))));
+    }
+    if let Some(from_file) = &alias_opts.from_file {
+        if from_file.as_os_str() == "-" && slack.token_from_stdin() {
+            return Err(Error::InvalidArguments(
+                "Can't read both the token and aliases from STDIN".into(),
+            ));
+        }
+        let content = read_to_string(from_file)
+            .map_err(|e| Error::Io(format!("Could not read aliases from {:?}", from_file), e))?;
+        pairs.extend(parse_alias_pairs(&content));
+    }
+
+    let existing: std::collections::HashSet<String> =
+        get_emoji(client, &slack.workspace, &slack.token)
+            .map_err(|e| Error::Api("Could not get emojis".into(), e))?
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+
+    let pb = progress.start(pairs.len() as u64, PROGRESS_TEMPLATE);
+
+    let rate_limiter = RateLimiter::new(20.0); // 20 aliases / s
+    let (mut added, mut failed) = (0, 0);
+
+    for (line_no, pair) in track(&pb, pairs.into_iter()) {
+        let location = if line_no > 0 {
+            format!("line {}: ", line_no)
+        } else {
+            String::new()
+        };
+        let (alias_name, target_name) = match pair {
+            Ok(pair) => pair,
+            Err(e) => {
+                pb.println(&format!("{}{}", location, e));
+                failed += 1;
+                continue;
+            }
+        };
+        if !existing.contains(&target_name) {
+            pb.println(&format!(
+                "{}{}: target {} does not exist",
+                location, alias_name, target_name
+            ));
+            failed += 1;
+            continue;
+        }
+
+        pb.set_message(&alias_name);
+        if global_opts.verbose {
+            pb.println(&format!("Adding alias {} for {}", alias_name, target_name));
+        }
+
+        match add_alias(
+            client,
+            &slack.workspace,
+            &slack.token,
+            &alias_name,
+            &target_name,
+        ) {
+            Ok(()) => added += 1,
+            Err(e) => {
+                pb.println(&format!(
+                    "{}{}: Could not add alias: {}",
+                    location, alias_name, e
+                ));
+                failed += 1;
+            }
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message(&format!("Done! {} added, {} failed", added, failed));
+
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod alias_tests {
+    use super::*;
+
+    #[test]
+    fn pairs() {
+        let pairs = parse_alias_pairs("parrot partyparrot\n\n  wave\tblob-wave \nbroken\na b c\n");
+        assert_eq!(
+            pairs,
+            vec![
+                (1, Ok(("parrot".to_string(), "partyparrot".to_string()))),
+                (3, Ok(("wave".to_string(), "blob-wave".to_string()))),
+                (4, Err("expected 2 columns, found 1".to_string())),
+                (5, Err("expected 2 columns, found 3".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! `analyze`
+
+use crate::*;
+use std::fs::read;
+
+pub(crate) fn analyze(
+    analyze_opts: AnalyzeOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = analyze_opts.global + global_opts;
+    let base_path = &analyze_opts.path;
+    let emoji: Vec<Emoji> = read_emoji_dir(base_path)
+        .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+        .filter(|e| e.is_alias == 0)
+        .collect();
+
+    let names = read_names_file(base_path);
+
+    let pb = progress.start(emoji.len() as u64, PROGRESS_TEMPLATE);
+    let (mut analyzed, mut animated, mut missing, mut failed) = (0, 0, 0, 0);
+    for e in track(&pb, emoji.iter().take_while(|_| !interrupted())) {
+        pb.set_message(&e.name);
+        let image = match find_image(base_path, e, &names) {
+            Some(image) => image,
+            None => {
+                if global_opts.verbose {
+                    pb.println(&format!("{}: No image found", e.name));
+                }
+                missing += 1;
+                continue;
+            }
+        };
+        let bytes = match read(&image) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                pb.println(&format!(
+                    "{}: Could not read {:?}: {}",
+                    e.name, image, error
+                ));
+                failed += 1;
+                continue;
+            }
+        };
+        // the metadata stays in the directory itself with every layout
+        let json_path = metadata_path(&image_path(base_path, e));
+        if let Err(error) = write_image_digest(&json_path, &bytes) {
+            pb.println(&format!("{}: Could not update metadata: {}", e.name, error));
+            failed += 1;
+            continue;
+        }
+        analyzed += 1;
+        if image_animated(&bytes) == Some(true) {
+            animated += 1;
+        }
+    }
+
+    pb.finish_with_message(&format!(
+        "Done! {} analyzed, {} of them animated, {} without image, {} failed",
+        analyzed, animated, missing, failed
+    ));
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod analyze_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    /// A 300×128 GIF with the given number of frames
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut gif = b"GIF89a\x2c\x01\x80\0\x80\0\0".to_vec();
+        gif.extend([0; 6]); // global color table
+        gif.extend(b"!\xff\x0bNETSCAPE2.0\x03\x01\0\0\0");
+        for _ in 0..frames {
+            gif.extend(b"!\xf9\x04\0\x0a\0\0\0");
+            gif.extend(b",\0\0\0\0\x2c\x01\x80\0\x80\0\0\0\0\0\0");
+            gif.extend(b"\x02\x02\x4c\x01\0");
+        }
+        gif.push(b';');
+        gif
+    }
+
+    #[test]
+    fn animation() {
+        assert_eq!(image_animated(&gif(1)), Some(false));
+        assert_eq!(image_animated(&gif(2)), Some(true));
+        assert_eq!(image_animated(&gif(2)[..60]), None);
+
+        let png = |chunk: &[u8]| {
+            let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+            png.extend([0; 13 + 4]);
+            png.extend(chunk);
+            png
+        };
+        assert_eq!(image_animated(&png(b"\0\0\0\0IDAT")), Some(false));
+        assert_eq!(
+            image_animated(&png(b"\0\0\0\x08acTL\0\0\0\x0c\0\0\0\0")),
+            Some(true)
+        );
+        assert_eq!(image_animated(&png(b"")), None);
+
+        let webp = |flags: u8| [&b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0"[..], &[flags]].concat();
+        assert_eq!(image_animated(&webp(0x02)), Some(true));
+        assert_eq!(image_animated(&webp(0x10)), Some(false));
+        assert_eq!(image_animated(&[0xff, 0xd8, 0xff]), Some(false));
+    }
+
+    #[test]
+    fn records_in_metadata() {
+        let dir = TestDir::new("analyze-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let mut emoji = Emoji::new("parrot");
+        emoji
+            .unknown_fields
+            .insert("is_bad".into(), serde_json::Value::Bool(false));
+        std::fs::write(
+            dir.path.join("parrot.json"),
+            serde_json::to_string(&emoji).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.path.join("parrot.gif"), gif(3)).unwrap();
+
+        let opts = AnalyzeOptions {
+            global: GlobalOptions::default(),
+            path: dir.path.to_path_buf(),
+        };
+        let summary = analyze(opts, GlobalOptions::default(), ProgressMode::None).unwrap();
+        assert_eq!(summary, Summary::done());
+
+        let read: Vec<Emoji> = read_emoji_dir(dir.path).unwrap().collect();
+        assert_eq!(read[0].animated, Some(true));
+        assert_eq!((read[0].width, read[0].height), (Some(300), Some(128)));
+        assert_eq!(read[0].image_bytes, Some(gif(3).len() as u64));
+        assert!(read[0].unknown_fields.contains_key("is_bad"));
+
+        let mut still = Emoji::new("still");
+        still.animated = Some(false);
+        let stats = Stats::new(&[read[0].clone(), still, Emoji::new("unknown")]);
+        assert_eq!((stats.animated, stats.analyzed), (1, 2));
+        assert_eq!(stats.top_animators[0].name, "M3t0r");
+        assert!(stats
+            .to_string()
+            .contains("1 of 2 analyzed emoji are animated"));
+    }
+
+    #[test]
+    fn other_layouts() {
+        let dir = TestDir::new("analyze-layout-test-dir");
+        let hash = sha256_hex(&gif(2));
+        let object = object_path(dir.path, &hash);
+        std::fs::create_dir_all(dir.path.join("M3t0r")).unwrap();
+        std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+        for name in ["parrot", "blob"] {
+            std::fs::write(
+                dir.path.join(name).with_extension("json"),
+                serde_json::to_string(&Emoji::new(name)).unwrap(),
+            )
+            .unwrap();
+        }
+        // downloaded with --by-user and with --cas
+        std::fs::write(dir.path.join("M3t0r").join("parrot.gif"), gif(1)).unwrap();
+        std::fs::write(&object, gif(2)).unwrap();
+        std::fs::write(
+            dir.path.join(NAMES_FILE),
+            serde_json::json!({ "blob": hash }).to_string(),
+        )
+        .unwrap();
+
+        let opts = AnalyzeOptions {
+            global: GlobalOptions::default(),
+            path: dir.path.to_path_buf(),
+        };
+        let summary = analyze(opts, GlobalOptions::default(), ProgressMode::None).unwrap();
+        assert_eq!(summary, Summary::done());
+        let mut read: Vec<Emoji> = read_emoji_dir(dir.path).unwrap().collect();
+        read.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(read[0].animated, Some(true));
+        assert_eq!(read[0].image_sha256, Some(hash));
+        assert_eq!(read[1].animated, Some(false));
+    }
+}
@@ -0,0 +1,136 @@
+//! `audit`
+
+use crate::*;
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct AuditFinding {
+    name: String,
+    detail: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct AuditReport {
+    /// Aliases pointing to emoji that don't exist anymore
+    broken_aliases: Vec<AuditFinding>,
+    /// Images not hosted on Slack's CDN or not served via https
+    unexpected_urls: Vec<AuditFinding>,
+    /// Custom emoji with the same name as a standard emoji
+    shadowed_standard_emoji: Vec<AuditFinding>,
+}
+
+/// Whether an URL points to Slack's CDN via https
+fn is_slack_cdn_url(url: &str) -> bool {
+    match reqwest::Url::parse(url) {
+        Ok(url) => {
+            let host = url.host_str().unwrap_or_default();
+            url.scheme() == "https"
+                && ["slack-edge.com", "slack.com"]
+                    .iter()
+                    .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+        }
+        Err(_) => false,
+    }
+}
+
+impl AuditReport {
+    pub fn new(emoji: &[Emoji]) -> AuditReport {
+        let names: std::collections::HashSet<&str> =
+            emoji.iter().map(|e| e.name.as_str()).collect();
+        let finding = |e: &Emoji, detail: String| AuditFinding {
+            name: e.name.clone(),
+            detail,
+        };
+
+        AuditReport {
+            broken_aliases: emoji
+                .iter()
+                .filter(|e| e.is_alias != 0 && !names.contains(e.alias_for.as_str()))
+                .map(|e| finding(e, format!("points to missing {}", e.alias_for)))
+                .collect(),
+            unexpected_urls: emoji
+                .iter()
+                .filter(|e| e.is_alias == 0 && !is_slack_cdn_url(&e.url))
+                .map(|e| finding(e, e.url.clone()))
+                .collect(),
+            // the shortcodes are GitHub's, which match Slack's for nearly all standard emoji
+            shadowed_standard_emoji: emoji
+                .iter()
+                .filter_map(|e| {
+                    emojis::get_by_shortcode(&e.name)
+                        .map(|standard| finding(e, standard.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    /// How many findings there are
+    pub fn len(&self) -> usize {
+        self.broken_aliases.len() + self.unexpected_urls.len() + self.shadowed_standard_emoji.len()
+    }
+}
+
+impl std::fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (title, findings) in &[
+            ("Broken aliases", &self.broken_aliases),
+            ("Unexpected image URLs", &self.unexpected_urls),
+            ("Shadowed standard emoji", &self.shadowed_standard_emoji),
+        ] {
+            writeln!(f, "{} ({}):", title, findings.len())?;
+            for finding in findings.iter() {
+                writeln!(f, "  {}: {}", finding.name, finding.detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn audit(client: &RetryingClient, audit_opts: AuditOptions) -> Result<Summary, Error> {
+    let emoji = audit_opts.source.load(client)?;
+    log::debug!("Auditing {} emoji", emoji.len());
+
+    let report = AuditReport::new(&emoji);
+    match audit_opts.output {
+        OutputFormat::Json => print_json(&report)?,
+        OutputFormat::Text => print!("{}", report),
+    }
+    Ok(Summary::failed(report.len()))
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert!(is_slack_cdn_url(
+            "https://emoji.slack-edge.com/T0123/parrot/abc.gif"
+        ));
+        assert!(!is_slack_cdn_url("http://emoji.slack-edge.com/parrot.gif"));
+        assert!(!is_slack_cdn_url("https://evil-slack-edge.com/parrot.gif"));
+        assert!(!is_slack_cdn_url("alias:parrot"));
+    }
+
+    #[test]
+    fn findings() {
+        let mut alias = Emoji::new("broken");
+        alias.is_alias = 1;
+        alias.alias_for = "gone".into();
+        alias.url = "alias:gone".into();
+        let mut smile = Emoji::new("smile");
+        smile.url = "https://emoji.slack-edge.com/T0123/smile/abc.png".into();
+
+        let report = AuditReport::new(&[alias, smile]);
+        assert_eq!(
+            report.broken_aliases,
+            vec![AuditFinding {
+                name: "broken".into(),
+                detail: "points to missing gone".into()
+            }]
+        );
+        assert!(report.unexpected_urls.is_empty());
+        assert_eq!(report.shadowed_standard_emoji.len(), 1);
+        assert_eq!(report.shadowed_standard_emoji[0].name, "smile");
+        assert_ne!(report.len(), 0);
+    }
+}
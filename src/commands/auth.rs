@@ -0,0 +1,50 @@
+//! `auth login` and `auth logout`
+
+use crate::*;
+
+#[cfg(feature = "keyring")]
+pub(crate) fn login(
+    client: &RetryingClient,
+    login_opts: AuthLoginOptions,
+) -> Result<Summary, Error> {
+    if cfg!(not(unix)) {
+        return Err(Error::InvalidArguments(KEYRING_UNSUPPORTED.into()));
+    }
+    let workspace = &login_opts.workspace;
+    let token = prompt_hidden(&format!("Token for {}: ", workspace))
+        .map_err(|e| Error::Io("Could not read the token".into(), e))?;
+    if token.is_empty() {
+        return Err(Error::InvalidArguments(
+            "No token given, nothing was stored".into(),
+        ));
+    }
+
+    let auth = auth_test(client, workspace, &token).map_err(|e| {
+        Error::Api(
+            format!("The token doesn't work for {}, it wasn't stored", workspace),
+            e,
+        )
+    })?;
+    store_keyring_token(workspace, &token)
+        .map_err(|e| Error::Failed(format!("Could not store the token: {}", e)))?;
+    log::info!(
+        "Stored the token of {} in {} for {}",
+        auth.user,
+        auth.team,
+        workspace
+    );
+    Ok(Summary::done())
+}
+
+#[cfg(feature = "keyring")]
+pub(crate) fn logout(logout_opts: AuthLoginOptions) -> Result<Summary, Error> {
+    if cfg!(not(unix)) {
+        return Err(Error::InvalidArguments(KEYRING_UNSUPPORTED.into()));
+    }
+    match delete_keyring_token(&logout_opts.workspace) {
+        Ok(true) => log::info!("Removed the token for {}", logout_opts.workspace),
+        Ok(false) => log::info!("No token stored for {}", logout_opts.workspace),
+        Err(e) => return Err(Error::Failed(format!("Could not remove the token: {}", e))),
+    }
+    Ok(Summary::done())
+}
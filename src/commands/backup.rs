@@ -0,0 +1,405 @@
+//! `backup` and `restore`
+
+use crate::*;
+use std::fs::{remove_file, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+pub(crate) fn backup(
+    client: &RetryingClient,
+    backup_opts: BackupOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = backup_opts.global + global_opts;
+    let slack = backup_opts.slack;
+    let base_path = backup_opts
+        .path
+        .unwrap_or_else(|| PathBuf::from(workspace_name(&slack.workspace)));
+
+    if base_path.exists() && !base_path.is_dir() {
+        return Err(Error::InvalidArguments(format!(
+            "Specified path is not a directory: {:?}",
+            base_path
+        )));
+    }
+
+    let mut emoji = get_emoji(client, &slack.workspace, &slack.token)
+        .map_err(|e| Error::Api("Could not get emojis".into(), e))?;
+    assign_file_names(&mut emoji);
+    let emoji_path_pairs: Vec<(&Emoji, PathBuf)> = emoji
+        .iter()
+        .filter(|e| e.is_alias == 0)
+        .map(|e| (e, image_path(&base_path, e)))
+        .collect();
+
+    // one bar for both phases: writing metadata, then downloading images
+    let pb = progress.start(
+        (emoji.len() + emoji_path_pairs.len()) as u64,
+        PROGRESS_TEMPLATE,
+    );
+
+    let mut ford_writer = FileOrDirectoryWriter::Directory(base_path, false);
+    pb.set_message("Writing metadata");
+    let mut failed = write_emoji_metadata(
+        &mut ford_writer,
+        &emoji,
+        &global_opts,
+        OutputFormat::Text,
+        &pb,
+    );
+    let settings = DownloadSettings {
+        force: backup_opts.force,
+        ..DownloadSettings::default()
+    };
+    failed += download_images(client, &emoji_path_pairs, &settings, &global_opts, &pb).failed;
+
+    pb.finish_with_message(&format!(
+        "Done! {} emoji in total, {} failed, {} retries",
+        emoji.len(),
+        failed,
+        client.retries()
+    ));
+    Ok(Summary::failed(failed))
+}
+
+pub(crate) fn restore(
+    client: &RetryingClient,
+    restore_opts: RestoreOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = restore_opts.global + global_opts;
+    let slack = restore_opts.slack;
+    require_resize(restore_opts.resize.auto_resize)?;
+    let base_path = &restore_opts.path;
+
+    let mut local: Vec<Emoji> = read_emoji_dir(base_path)
+        .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+        .collect();
+    local.sort_by_key(|e| e.created);
+    // aliases can only be created once the emoji they point to exist
+    let (aliases, emoji): (Vec<Emoji>, Vec<Emoji>) =
+        local.into_iter().partition(|e| e.is_alias != 0);
+
+    let images: std::collections::HashMap<String, PathBuf> = read_image_dir(base_path)
+        .map_err(|e| Error::Io("could not read images from directory".into(), e))?
+        .into_iter()
+        .collect();
+
+    let existing = get_emoji_names(client, &slack)?;
+
+    // remembers what was restored so an interrupted run can be resumed, even with --replace
+    let state_path = base_path.join(format!(
+        ".slack-emoji-restore-{}",
+        workspace_name(&slack.workspace)
+    ));
+    let restored: std::collections::HashSet<String> = read_lines(&state_path)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let mut state_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&state_path)
+        .map_err(|e| Error::Io(format!("Could not open {:?}", state_path), e))?;
+
+    let pb = progress.start((emoji.len() + aliases.len()) as u64, PROGRESS_TEMPLATE);
+    let rate_limiter = RateLimiter::new(20.0); // 20 requests / s
+    let (mut added, mut skipped, mut failed) = (0, 0, 0);
+
+    for e in track(&pb, emoji.iter().chain(aliases.iter())) {
+        let name = normalize_emoji_name(&e.name);
+        if restored.contains(&name) {
+            skipped += 1;
+            continue;
+        }
+        pb.set_message(&name);
+
+        if existing.contains(&name) {
+            if !restore_opts.replace {
+                pb.println(&format!("{}: already exists, skipping", name));
+                skipped += 1;
+                continue;
+            }
+            if global_opts.verbose {
+                pb.println(&format!("Removing existing {}", name));
+            }
+            let removed = remove_emoji(client, &slack.workspace, &slack.token, &name);
+            rate_limiter.wait();
+            if let Err(error) = removed {
+                pb.println(&format!(
+                    "{}: Could not remove existing emoji: {}",
+                    name, error
+                ));
+                failed += 1;
+                continue;
+            }
+        }
+
+        let result = if e.is_alias != 0 {
+            if global_opts.verbose {
+                pb.println(&format!("Adding alias {} for {}", name, e.alias_for));
+            }
+            add_alias(client, &slack.workspace, &slack.token, &name, &e.alias_for)
+        } else {
+            let image = match images.get(&name).map(read) {
+                Some(Ok(image)) => image,
+                Some(Err(error)) => {
+                    pb.println(&format!("{}: Could not read image: {}", name, error));
+                    failed += 1;
+                    continue;
+                }
+                None => {
+                    pb.println(&format!("{}: No image found", name));
+                    failed += 1;
+                    continue;
+                }
+            };
+            let image = match fit_upload_limits(&name, image, restore_opts.resize.auto_resize, &*pb)
+            {
+                Ok(image) => image,
+                Err(error) => {
+                    pb.println(&format!("{}: {}", name, error));
+                    failed += 1;
+                    continue;
+                }
+            };
+            if global_opts.verbose {
+                pb.println(&format!("Uploading {}", name));
+            }
+            add_emoji(client, &slack.workspace, &slack.token, &name, image)
+        };
+        match result {
+            Ok(()) => {
+                added += 1;
+                writeln!(state_file, "{}", name).ok();
+            }
+            Err(error) => {
+                pb.println(&format!("{}: Could not restore: {}", name, error));
+                failed += 1;
+            }
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message(&format!(
+        "Done! {} restored, {} skipped, {} failed",
+        added, skipped, failed
+    ));
+
+    if failed > 0 {
+        log::info!("Re-run the same command to retry the failed emoji");
+    } else {
+        remove_file(&state_path).ok();
+    }
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod backup_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use structopt::StructOpt;
+
+    fn back_up(server: &MockServer, path: &std::path::Path) -> Result<Summary, Error> {
+        let args = [
+            "slack-emoji",
+            "backup",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+            path.to_str().unwrap(),
+        ];
+        let backup_opts = match Cli::from_iter_safe(args).unwrap().command {
+            Commands::Backup(backup_opts) => backup_opts,
+            command => panic!("not a backup command: {:?}", command),
+        };
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        backup(
+            &client,
+            backup_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+    }
+
+    #[test]
+    fn stores_metadata_and_images() {
+        let dir = TestDir::new("backup-test-dir");
+        let gif = MockResponse {
+            status: 200,
+            headers: vec![("Content-Type", "image/gif".into())],
+            body: b"GIF89a".to_vec(),
+        };
+        let images = MockServer::start(vec![gif, MockResponse::status(404)]);
+        let server = MockServer::start(vec![MockResponse::json(serde_json::json!({
+            "ok": true,
+            "custom_emoji_total_count": 3,
+            "paging": {"count": 1000, "page": 1, "pages": 1},
+            "emoji": [
+                {"name": "parrot", "url": format!("{}/parrot.gif", images.url), "created": 1},
+                {"name": "gone", "url": format!("{}/gone.gif", images.url), "created": 2},
+                {
+                    "name": "party",
+                    "url": "alias:parrot",
+                    "is_alias": 1,
+                    "alias_for": "parrot",
+                    "created": 3
+                },
+            ],
+        }))]);
+
+        let summary = back_up(&server, dir.path).unwrap();
+
+        // the image that could not be downloaded
+        assert_eq!(summary.failed, 1);
+        assert_eq!(server.finish(), vec!["POST /api/emoji.adminList"]);
+        assert_eq!(images.finish(), vec!["GET /parrot.gif", "GET /gone.gif"]);
+        for name in &["parrot.json", "gone.json", "party.json"] {
+            assert!(dir.path.join(name).is_file(), "{} is missing", name);
+        }
+        assert_eq!(
+            std::fs::read(dir.path.join("parrot.gif")).unwrap(),
+            b"GIF89a"
+        );
+        assert!(!dir.path.join("gone.gif").exists());
+    }
+
+    #[test]
+    fn needs_a_directory() {
+        let dir = TestDir::new("backup-file-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let file = dir.path.join("backup");
+        std::fs::write(&file, b"").unwrap();
+        let server = MockServer::start(vec![]);
+
+        assert!(matches!(
+            back_up(&server, &file),
+            Err(Error::InvalidArguments(_))
+        ));
+        assert!(server.finish().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod restore_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use structopt::StructOpt;
+
+    fn restore_from(server: &MockServer, path: &std::path::Path) -> Result<Summary, Error> {
+        let args = [
+            "slack-emoji",
+            "restore",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+            path.to_str().unwrap(),
+        ];
+        let mut restore_opts = match Cli::from_iter_safe(args).unwrap().command {
+            Commands::Restore(restore_opts) => restore_opts,
+            command => panic!("not a restore command: {:?}", command),
+        };
+        // names the file that remembers what was restored
+        restore_opts.slack.resolve(None).unwrap();
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        restore(
+            &client,
+            restore_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+    }
+
+    fn admin_list(names: &[&str]) -> MockResponse {
+        let emoji: Vec<serde_json::Value> = names
+            .iter()
+            .map(|name| serde_json::json!({"name": name, "url": "https://x/a.gif", "created": 1}))
+            .collect();
+        MockResponse::json(serde_json::json!({
+            "ok": true,
+            "custom_emoji_total_count": emoji.len(),
+            "paging": {"count": 1000, "page": 1, "pages": 1},
+            "emoji": emoji,
+        }))
+    }
+
+    #[test]
+    fn uploads_and_resumes() {
+        let dir = TestDir::new("restore-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let write_json = |emoji: &Emoji| {
+            std::fs::write(
+                dir.path.join(&emoji.name).with_extension("json"),
+                serde_json::to_string(emoji).unwrap(),
+            )
+            .unwrap();
+        };
+        let mut alias = Emoji::new("party");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+        write_json(&alias);
+        write_json(&Emoji::new("parrot"));
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        write_json(&Emoji::new("taken"));
+        std::fs::write(dir.path.join("taken.gif"), b"GIF89a").unwrap();
+        write_json(&Emoji::new("imageless"));
+
+        let server = MockServer::start(vec![
+            admin_list(&["taken"]),
+            MockResponse::json(serde_json::json!({"ok": true})),
+            MockResponse::json(serde_json::json!({"ok": true})),
+        ]);
+        let summary = restore_from(&server, dir.path).unwrap();
+
+        // the emoji without an image
+        assert_eq!(summary.failed, 1);
+        // the alias comes after what it points to, taken already exists
+        assert_eq!(
+            server.finish(),
+            vec![
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.add",
+                "POST /api/emoji.add"
+            ]
+        );
+
+        // a second run only retries what failed
+        let server = MockServer::start(vec![admin_list(&["taken", "parrot", "party"])]);
+        let summary = restore_from(&server, dir.path).unwrap();
+        assert_eq!(summary.failed, 1);
+        assert_eq!(server.finish(), vec!["POST /api/emoji.adminList"]);
+    }
+
+    #[test]
+    fn reports_rejected_uploads() {
+        let dir = TestDir::new("restore-failure-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(
+            dir.path.join("parrot.json"),
+            serde_json::to_string(&Emoji::new("parrot")).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        let server = MockServer::start(vec![
+            admin_list(&[]),
+            MockResponse::json(serde_json::json!({"ok": false, "error": "error_bad_upload"})),
+        ]);
+
+        let summary = restore_from(&server, dir.path).unwrap();
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(
+            server.finish(),
+            vec!["POST /api/emoji.adminList", "POST /api/emoji.add"]
+        );
+        // kept to resume from
+        assert!(dir.path.join(".slack-emoji-restore-acme").is_file());
+    }
+}
@@ -0,0 +1,130 @@
+//! `check`
+
+use crate::*;
+
+pub(crate) fn check(
+    client: &RetryingClient,
+    check_opts: CheckOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = check_opts.global + global_opts;
+
+    let emoji: Vec<Emoji> = check_opts
+        .source
+        .load(client)?
+        .into_iter()
+        .filter(|e| !e.url.starts_with("alias:")) // aliases have no image
+        .collect();
+
+    let pb = progress.start(emoji.len() as u64, PROGRESS_TEMPLATE);
+    let rate_limiter = RateLimiter::new(20.0); // 20 requests / s
+    let mut broken = 0;
+
+    for e in track(&pb, emoji.iter()) {
+        pb.set_message(&e.name);
+        if global_opts.verbose {
+            pb.println(&format!("Checking {}", e.url));
+        }
+
+        let res = client
+            .head(&e.url)
+            .timeout(client.image_timeout)
+            .build()
+            .and_then(|req| client.execute_request(req, ""))
+            .and_then(|res| res.error_for_status());
+        if let Err(error) = res {
+            let reason = match error.status() {
+                Some(status) => status.to_string(),
+                None => client.describe_error(error),
+            };
+            pb.println(&format!("{}: {} is broken: {}", e.name, e.url, reason));
+            broken += 1;
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message(&format!("Done! {} of {} broken", broken, emoji.len()));
+
+    Ok(Summary::failed(broken))
+}
+
+#[cfg(test)]
+mod check_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use structopt::StructOpt;
+
+    fn check_emoji(server: &MockServer, source: &[&str]) -> Result<Summary, Error> {
+        let check_opts = match Cli::from_iter_safe(["slack-emoji", "check"].iter().chain(source))
+            .unwrap()
+            .command
+        {
+            Commands::Check(check_opts) => check_opts,
+            command => panic!("not a check command: {:?}", command),
+        };
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        check(
+            &client,
+            check_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+    }
+
+    #[test]
+    fn finds_broken_images() {
+        let images = MockServer::start(vec![MockResponse::status(200), MockResponse::status(404)]);
+        let server = MockServer::start(vec![MockResponse::json(serde_json::json!({
+            "ok": true,
+            "custom_emoji_total_count": 3,
+            "paging": {"count": 1000, "page": 1, "pages": 1},
+            "emoji": [
+                {"name": "parrot", "url": format!("{}/parrot.gif", images.url), "created": 1},
+                {"name": "gone", "url": format!("{}/gone.gif", images.url), "created": 2},
+                {
+                    "name": "party",
+                    "url": "alias:parrot",
+                    "is_alias": 1,
+                    "alias_for": "parrot",
+                    "created": 3
+                },
+            ],
+        }))]);
+
+        let summary = check_emoji(&server, &["--workspace", "acme", "--token", "xoxs-1"]).unwrap();
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(server.finish(), vec!["POST /api/emoji.adminList"]);
+        // aliases have no image to check
+        assert_eq!(images.finish(), vec!["HEAD /parrot.gif", "HEAD /gone.gif"]);
+    }
+
+    #[test]
+    fn checks_a_directory() {
+        let dir = TestDir::new("check-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let images = MockServer::start(vec![MockResponse::status(410)]);
+        let mut gone = Emoji::new("gone");
+        gone.url = format!("{}/gone.gif", images.url);
+        std::fs::write(
+            dir.path.join("gone.json"),
+            serde_json::to_string(&gone).unwrap(),
+        )
+        .unwrap();
+
+        let summary = check_emoji(&images, &[dir.path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(images.finish(), vec!["HEAD /gone.gif"]);
+
+        let missing = dir.path.join("missing");
+        let server = MockServer::start(vec![]);
+        assert!(matches!(
+            check_emoji(&server, &[missing.to_str().unwrap()]),
+            Err(Error::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,50 @@
+//! `completions`
+
+use crate::*;
+use std::convert::TryInto;
+use structopt::StructOpt;
+
+/// Generates the completion script of the whole CLI for a shell
+fn completion_script(shell: structopt::clap::Shell) -> String {
+    let mut script = vec![];
+    Cli::clap().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut script);
+    String::from_utf8_lossy(&script).into_owned()
+}
+
+pub(crate) fn completions(completions_opts: CompletionsOptions) -> Result<Summary, Error> {
+    let output = &completions_opts.output;
+    let mut ford_writer: FileOrDirectoryWriter = match output.clone().try_into() {
+        Ok(FileOrDirectoryWriter::Directory(..)) => {
+            return Err(Error::InvalidArguments(format!(
+                "Can't write completions to a directory: {:?}",
+                output
+            )))
+        }
+        Ok(ford_writer) => ford_writer,
+        Err(e) => return Err(Error::Io(format!("Could not open {:?}", output), e)),
+    };
+
+    let script = completion_script(completions_opts.shell);
+    ford_writer
+        .write(env!("CARGO_PKG_NAME"), script.trim_end().to_string())
+        .map_err(|e| {
+            Error::Failed(format!(
+                "Could not write completions to {:?}: {}",
+                output, e
+            ))
+        })?;
+    Ok(Summary::done())
+}
+
+#[cfg(test)]
+mod completions_tests {
+    use super::*;
+
+    #[test]
+    fn covers_subcommands() {
+        let script = completion_script(structopt::clap::Shell::Bash);
+        for word in &["audit", "sync", "push", "--workspace", "--compare-images"] {
+            assert!(script.contains(word), "{} is not completed", word);
+        }
+    }
+}
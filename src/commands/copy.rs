@@ -0,0 +1,224 @@
+//! `copy`, from one workspace to another
+
+use crate::*;
+
+pub(crate) fn copy(
+    client: &RetryingClient,
+    copy_opts: CopyOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = copy_opts.global + global_opts;
+    require_resize(copy_opts.resize.auto_resize)?;
+    check_token_files(&[
+        copy_opts.from_token_file.as_deref(),
+        copy_opts.to_token_file.as_deref(),
+    ])?;
+    let from_token = workspace_token(
+        &copy_opts.from_workspace,
+        copy_opts.from_token.as_deref(),
+        copy_opts.from_token_file.as_deref(),
+        "from-token",
+    )
+    .map_err(Error::InvalidArguments)?;
+    let to_token = workspace_token(
+        &copy_opts.to_workspace,
+        copy_opts.to_token.as_deref(),
+        copy_opts.to_token_file.as_deref(),
+        "to-token",
+    )
+    .map_err(Error::InvalidArguments)?;
+    let (to_workspace, to_token) = (&copy_opts.to_workspace, &to_token);
+
+    let get = |workspace: &str, token: &str| {
+        get_emoji(client, workspace, token)
+            .map_err(|e| Error::Api(format!("Could not get emojis from {}", workspace), e))
+    };
+    let source = get(&copy_opts.from_workspace, &from_token)?;
+    let existing: std::collections::HashSet<String> = get(to_workspace, to_token)?
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+
+    // aliases can only be created once the emoji they point to exist
+    let (aliases, emoji): (Vec<&Emoji>, Vec<&Emoji>) = source.iter().partition(|e| e.is_alias != 0);
+
+    let pb = progress.start(source.len() as u64, PROGRESS_TEMPLATE);
+    let rate_limiter = RateLimiter::new(20.0); // 20 requests / s
+    let (mut copied, mut skipped, mut failed) = (0, 0, 0);
+
+    for e in track(&pb, emoji.into_iter().chain(aliases)) {
+        if existing.contains(&e.name) {
+            if global_opts.verbose {
+                pb.println(&format!(
+                    "{}: already exists in {}, skipping",
+                    e.name, to_workspace
+                ));
+            }
+            skipped += 1;
+            continue;
+        }
+        pb.set_message(&e.name);
+
+        let result = if e.is_alias != 0 {
+            if global_opts.verbose {
+                pb.println(&format!("Adding alias {} for {}", e.name, e.alias_for));
+            }
+            add_alias(client, to_workspace, to_token, &e.name, &e.alias_for)
+        } else {
+            if global_opts.verbose {
+                pb.println(&format!("Copying {} from {}", e.name, e.url));
+            }
+            let image = download_image(client, &e.url);
+            rate_limiter.wait();
+            let image = image
+                .map(|image| fit_upload_limits(&e.name, image, copy_opts.resize.auto_resize, &*pb));
+            match image {
+                Ok(Ok(image)) => add_emoji(client, to_workspace, to_token, &e.name, image),
+                Ok(Err(error)) => {
+                    pb.println(&format!("{}: {}", e.name, error));
+                    failed += 1;
+                    continue;
+                }
+                Err(error) => Err(error.into()),
+            }
+        };
+        match result {
+            Ok(()) => copied += 1,
+            Err(error) => {
+                pb.println(&format!("{}: Could not copy: {}", e.name, error));
+                failed += 1;
+            }
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message(&format!(
+        "Done! {} copied, {} skipped, {} failed",
+        copied, skipped, failed
+    ));
+
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod copy_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer};
+    use structopt::StructOpt;
+
+    fn copy_options() -> CopyOptions {
+        let args = [
+            "slack-emoji",
+            "copy",
+            "--from-workspace",
+            "acme",
+            "--from-token",
+            "xoxs-1",
+            "--to-workspace",
+            "other",
+            "--to-token",
+            "xoxs-2",
+        ];
+        match Cli::from_iter_safe(args).unwrap().command {
+            Commands::Copy(copy_opts) => copy_opts,
+            command => panic!("not a copy command: {:?}", command),
+        }
+    }
+
+    #[test]
+    fn copies_what_is_missing() {
+        let gif = MockResponse {
+            status: 200,
+            headers: vec![("Content-Type", "image/gif".into())],
+            body: b"GIF89a".to_vec(),
+        };
+        let images = MockServer::start(vec![gif, MockResponse::status(404)]);
+        let image = |name: &str, created: u64| {
+            serde_json::json!({
+                "name": name,
+                "url": format!("{}/{}.gif", images.url, name),
+                "created": created
+            })
+        };
+        let alias = serde_json::json!({
+            "name": "party",
+            "url": "alias:parrot",
+            "is_alias": 1,
+            "alias_for": "parrot",
+            "created": 4
+        });
+        let admin_list = |emoji: Vec<serde_json::Value>| {
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "custom_emoji_total_count": emoji.len(),
+                "paging": {"count": 1000, "page": 1, "pages": 1},
+                "emoji": emoji,
+            }))
+        };
+        let server = MockServer::start(vec![
+            admin_list(vec![
+                alias,
+                image("parrot", 1),
+                image("blob", 2),
+                image("broken", 3),
+            ]),
+            admin_list(vec![image("blob", 1)]),
+            MockResponse::json(serde_json::json!({"ok": true})),
+            MockResponse::json(serde_json::json!({"ok": true})),
+        ]);
+
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        let summary = copy(
+            &client,
+            copy_options(),
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+        .unwrap();
+
+        // the image that could not be downloaded, blob already exists
+        assert_eq!(summary.failed, 1);
+        assert_eq!(images.finish(), vec!["GET /parrot.gif", "GET /broken.gif"]);
+        // the alias is added once what it points to exists
+        assert_eq!(
+            server.finish(),
+            vec![
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.add",
+                "POST /api/emoji.add"
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_both_workspaces() {
+        let server = MockServer::start(vec![
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "custom_emoji_total_count": 0,
+                "paging": {"count": 1000, "page": 1, "pages": 1},
+                "emoji": [],
+            })),
+            MockResponse::json(serde_json::json!({"ok": false, "error": "invalid_auth"})),
+        ]);
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        let result = copy(
+            &client,
+            copy_options(),
+            GlobalOptions::default(),
+            ProgressMode::None,
+        );
+
+        match result {
+            Err(Error::Api(message, _)) => {
+                assert_eq!(message, "Could not get emojis from other")
+            }
+            _ => panic!("copied without the destination's emoji"),
+        }
+        assert_eq!(server.finish().len(), 2);
+    }
+}
@@ -0,0 +1,366 @@
+//! `dedupe`
+
+use crate::*;
+use std::fs::read;
+use std::path::PathBuf;
+
+/// An emoji image that has byte-identical copies under other names
+#[derive(Debug, PartialEq)]
+struct Duplicate {
+    name: String,
+    path: PathBuf,
+    created: Timestamp,
+}
+
+/// Groups the images in a directory by their content, only groups with duplicates are returned
+///
+/// Groups are sorted by their number of members and then their size, largest first. Emoji
+/// within a group are sorted oldest first.
+fn find_duplicates(path: &std::path::Path) -> std::io::Result<Vec<(u64, Vec<Duplicate>)>> {
+    let created = created_by_name(path)?;
+
+    let mut groups: std::collections::HashMap<String, (u64, Vec<Duplicate>)> =
+        std::collections::HashMap::new();
+    for (name, path) in read_image_dir(path)? {
+        let bytes = read(&path)?;
+        let group = groups
+            .entry(sha256_hex(&bytes))
+            .or_insert_with(|| (bytes.len() as u64, vec![]));
+        group.1.push(Duplicate {
+            created: created.get(&name).copied().unwrap_or(Timestamp(u128::MAX)),
+            name,
+            path,
+        });
+    }
+
+    let mut groups: Vec<(u64, Vec<Duplicate>)> = groups
+        .into_values()
+        .filter(|(_, members)| members.len() > 1)
+        .collect();
+    for (_, members) in &mut groups {
+        members.sort_by(|a, b| a.created.cmp(&b.created).then(a.name.cmp(&b.name)));
+    }
+    groups.sort_by(|(size_a, a), (size_b, b)| {
+        b.len()
+            .cmp(&a.len())
+            .then(size_b.cmp(size_a))
+            .then(a[0].name.cmp(&b[0].name))
+    });
+    Ok(groups)
+}
+
+/// When each emoji with metadata in a directory was added, by normalized name
+fn created_by_name(
+    path: &std::path::Path,
+) -> std::io::Result<std::collections::HashMap<String, Timestamp>> {
+    Ok(read_emoji_dir(path)?
+        .map(|e| (normalize_emoji_name(&e.name), e.created))
+        .collect())
+}
+
+/// The difference hash of an image scaled down to 9×8 gray pixels, row by row
+///
+/// Each bit tells whether a pixel is darker than the one right of it, so images that look
+/// the same have hashes with few differing bits, even after recompressing or resizing.
+fn dhash(gray: &[u8; 72]) -> u64 {
+    let mut hash = 0;
+    for row in gray.chunks(9) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// Groups the indices of hashes that are within `max_distance` bits of each other
+///
+/// Similarity is transitive here, so the members of a group can be further apart.
+fn cluster_hashes(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut group_of: Vec<usize> = (0..hashes.len()).collect();
+    fn root(group_of: &mut [usize], mut i: usize) -> usize {
+        while group_of[i] != i {
+            group_of[i] = group_of[group_of[i]];
+            i = group_of[i];
+        }
+        i
+    }
+    for (i, a) in hashes.iter().enumerate() {
+        for (j, b) in hashes.iter().enumerate().skip(i + 1) {
+            if (a ^ b).count_ones() <= max_distance {
+                let (i, j) = (root(&mut group_of, i), root(&mut group_of, j));
+                group_of[j] = i;
+            }
+        }
+    }
+    let mut groups: std::collections::BTreeMap<usize, Vec<usize>> = Default::default();
+    for i in 0..hashes.len() {
+        groups.entry(root(&mut group_of, i)).or_default().push(i);
+    }
+    groups
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .collect()
+}
+
+/// Scales the first frame of an image down to 9×8 gray pixels via ImageMagick, for `dhash`
+#[cfg(feature = "resize")]
+fn image_gray_pixels(path: &std::path::Path) -> Result<[u8; 72], String> {
+    use std::convert::TryInto;
+    let image = read(path).map_err(|e| format!("Could not read it: {}", e))?;
+    let pixels = convert(
+        &image,
+        &[
+            "-[0]",
+            "-colorspace",
+            "Gray",
+            "-resize",
+            "9x8!",
+            "-depth",
+            "8",
+            "gray:-",
+        ],
+    )?;
+    pixels[..].try_into().map_err(|_| {
+        format!(
+            "ImageMagick returned {} bytes instead of 72 pixels",
+            pixels.len()
+        )
+    })
+}
+
+#[cfg(not(feature = "resize"))]
+fn image_gray_pixels(_path: &std::path::Path) -> Result<[u8; 72], String> {
+    Err(NO_RESIZE.to_string())
+}
+
+/// Images that look alike, each with how many bits its hash differs from the first one's
+type SimilarImages = Vec<(Duplicate, u32)>;
+
+/// Groups the images in a directory that look alike, with the distance of each to the first
+///
+/// Groups are sorted by their number of members, emoji within a group oldest first. Returns
+/// how many images couldn't be compared as well.
+fn find_similar(
+    path: &std::path::Path,
+    max_distance: u32,
+) -> std::io::Result<(Vec<SimilarImages>, usize)> {
+    let created = created_by_name(path)?;
+
+    let (mut images, mut hashes, mut failed) = (vec![], vec![], 0);
+    for (name, path) in read_image_dir(path)? {
+        match image_gray_pixels(&path).map(|gray| dhash(&gray)) {
+            Ok(hash) => {
+                hashes.push(hash);
+                images.push(Some(Duplicate {
+                    created: created.get(&name).copied().unwrap_or(Timestamp(u128::MAX)),
+                    name,
+                    path,
+                }));
+            }
+            Err(e) => {
+                log::error!("Could not compare {:?}: {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<(Duplicate, u64)>> = cluster_hashes(&hashes, max_distance)
+        .into_iter()
+        .map(|members| {
+            members
+                .into_iter()
+                .filter_map(|i| Some((images[i].take()?, hashes[i])))
+                .collect()
+        })
+        .collect();
+    for members in &mut groups {
+        members.sort_by(|(a, _), (b, _)| a.created.cmp(&b.created).then(a.name.cmp(&b.name)));
+    }
+    groups.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].0.name.cmp(&b[0].0.name)));
+
+    let groups = groups
+        .into_iter()
+        .map(|members| {
+            let first = members[0].1;
+            members
+                .into_iter()
+                .map(|(d, hash)| (d, (hash ^ first).count_ones()))
+                .collect()
+        })
+        .collect();
+    Ok((groups, failed))
+}
+
+/// Prints groups of similar images for `dedupe --fuzzy`, without changing anything
+fn dedupe_fuzzy(path: &std::path::Path, max_distance: u32) -> Result<Summary, Error> {
+    require_resize(true)?;
+    let (groups, failed) = find_similar(path, max_distance)
+        .map_err(|e| Error::Io("Could not read images".into(), e))?;
+
+    for members in &groups {
+        println!("{} similar images:", members.len());
+        for (d, distance) in members {
+            match distance {
+                0 => println!("  {}", d.path.display()),
+                distance => println!("  {} (distance {})", d.path.display(), distance),
+            }
+        }
+    }
+    let similar: usize = groups.iter().map(|members| members.len() - 1).sum();
+    log::info!(
+        "{} similar images in {} groups, review them before removing any",
+        similar,
+        groups.len()
+    );
+    Ok(Summary::failed(failed))
+}
+
+pub(crate) fn dedupe(
+    client: &RetryingClient,
+    dedupe_opts: DedupeOptions,
+) -> Result<Summary, Error> {
+    if dedupe_opts.fuzzy {
+        return dedupe_fuzzy(&dedupe_opts.path, dedupe_opts.max_distance);
+    }
+    let groups = find_duplicates(&dedupe_opts.path)
+        .map_err(|e| Error::Io("Could not read images".into(), e))?;
+
+    for (size, members) in &groups {
+        println!("{} identical images, {} bytes each:", members.len(), size);
+        for (i, d) in members.iter().enumerate() {
+            let role = match (dedupe_opts.fix, i) {
+                (false, _) => "",
+                (true, 0) => " (keep)",
+                (true, _) => " (replace with alias)",
+            };
+            println!("  {}{}", d.path.display(), role);
+        }
+    }
+    let duplicates: usize = groups.iter().map(|(_, members)| members.len() - 1).sum();
+    log::info!("{} duplicates in {} groups", duplicates, groups.len());
+
+    if !dedupe_opts.fix || duplicates == 0 {
+        return Ok(Summary::done());
+    }
+    if !dedupe_opts.yes {
+        log::info!("Re-run with --yes to replace the duplicates with aliases");
+        return Ok(Summary::done());
+    }
+
+    // both are guaranteed by structopt when fixing
+    let workspace = dedupe_opts.workspace.unwrap_or_default();
+    let token = dedupe_opts.token.unwrap_or_default();
+    let rate_limiter = RateLimiter::new(20.0); // 20 requests / s
+    let mut failed = 0;
+
+    for (_, members) in &groups {
+        let keeper = &members[0].name;
+        for d in &members[1..] {
+            log::debug!("Replacing {} with an alias for {}", d.name, keeper);
+            let replaced = remove_emoji(client, &workspace, &token, &d.name).and_then(|_| {
+                rate_limiter.wait();
+                add_alias(client, &workspace, &token, &d.name, keeper)
+            });
+            if let Err(e) = replaced {
+                log::error!("{}: Could not replace with alias: {}", d.name, e);
+                failed += 1;
+            }
+            rate_limiter.wait();
+        }
+    }
+
+    log::info!(
+        "Replaced {} duplicates, {} failed",
+        duplicates - failed,
+        failed
+    );
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod dedupe_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+    use structopt::StructOpt;
+
+    #[test]
+    fn groups() {
+        let dir = TestDir::new("dedupe-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        for (name, created) in &[("new", 300), ("old", 100), ("big", 200)] {
+            let mut emoji = Emoji::new(name);
+            emoji.created = (*created).into();
+            std::fs::write(
+                dir.path.join(name).with_extension("json"),
+                serde_json::to_string(&emoji).unwrap(),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.path.join("new.png"), b"same").unwrap();
+        std::fs::write(dir.path.join("old.png"), b"same").unwrap();
+        std::fs::write(dir.path.join("nometa.gif"), b"same").unwrap();
+        std::fs::write(dir.path.join("big.png"), b"bigger").unwrap();
+        std::fs::write(dir.path.join("big2.png"), b"bigger").unwrap();
+        std::fs::write(dir.path.join("unique.png"), b"unique").unwrap();
+
+        let groups = find_duplicates(dir.path).expect("could not find duplicates");
+        let names: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|(_, members)| members.iter().map(|d| d.name.as_str()).collect())
+            .collect();
+        assert_eq!(
+            names,
+            vec![vec!["old", "new", "nometa"], vec!["big", "big2"]]
+        );
+        assert_eq!(groups[0].0, 4);
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn perceptual_hashes() {
+        let gradient: [u8; 72] = std::array::from_fn(|i| (i % 9 * 20) as u8);
+        assert_eq!(dhash(&gradient), u64::MAX);
+        let mut recompressed = gradient;
+        recompressed[4] = 100; // now brighter than the pixel after it
+        assert_eq!((dhash(&gradient) ^ dhash(&recompressed)).count_ones(), 1);
+        assert_eq!(dhash(&[128; 72]), 0);
+    }
+
+    #[test]
+    fn clusters() {
+        let hashes = [0xff00, 0xf0f0_f0f0, 0xff01, 0xff03, 0xf0f0_f0f0, 0x1];
+        assert_eq!(cluster_hashes(&hashes, 1), vec![vec![0, 2, 3], vec![1, 4]]);
+        assert_eq!(cluster_hashes(&hashes, 0), vec![vec![1, 4]]);
+        assert!(cluster_hashes(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn fuzzy_options() {
+        let args = |args: &[&str]| Cli::from_iter_safe(args).map(|_| ());
+        assert!(args(&["slack-emoji", "dedupe", "--fuzzy", "dir"]).is_ok());
+        assert!(args(&["slack-emoji", "dedupe", "--max-distance", "8", "dir"]).is_err());
+        let fix = [
+            "slack-emoji",
+            "dedupe",
+            "--fuzzy",
+            "--fix",
+            "--workspace",
+            "x",
+            "dir",
+        ];
+        assert!(args(&[&fix[..], &["--token", "xoxs-1"]].concat()).is_err());
+
+        #[cfg(not(feature = "resize"))]
+        assert!(matches!(
+            dedupe_fuzzy(std::path::Path::new("dedupe-fuzzy-missing-dir"), 4),
+            Err(Error::InvalidArguments(_))
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+//! `delete`
+
+use crate::*;
+
+pub(crate) fn delete(
+    client: &RetryingClient,
+    delete_opts: DeleteOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = delete_opts.global + global_opts;
+    let slack = delete_opts.slack;
+
+    let mut names = delete_opts.names;
+    if let Some(from_file) = &delete_opts.from_file {
+        if from_file.as_os_str() == "-" && slack.token_from_stdin() {
+            return Err(Error::InvalidArguments(
+                "Can't read both the token and names from STDIN".into(),
+            ));
+        }
+        if from_file.as_os_str() == "-" && !delete_opts.yes {
+            return Err(Error::InvalidArguments(
+                "Reading names from STDIN requires --yes since we can't ask for confirmation"
+                    .into(),
+            ));
+        }
+        let lines = read_lines(from_file)
+            .map_err(|e| Error::Io(format!("Could not read names from {:?}", from_file), e))?;
+        names.extend(lines);
+    }
+
+    if names.is_empty() {
+        log::info!("No emoji to remove");
+        return Ok(Summary::done());
+    }
+
+    if !delete_opts.yes
+        && !confirm(&format!(
+            "Remove {} emoji from {}?",
+            names.len(),
+            slack.workspace
+        ))
+    {
+        return Err(Error::Failed("Aborted".into()));
+    }
+
+    let pb = progress.start(names.len() as u64, PROGRESS_TEMPLATE);
+
+    let rate_limiter = RateLimiter::new(20.0); // 20 removals / s
+    let mut failed = 0;
+
+    for name in track(&pb, names.iter()) {
+        pb.set_message(name);
+        if global_opts.verbose {
+            pb.println(&format!("Removing {}", name));
+        }
+
+        if let Err(e) = remove_emoji(client, &slack.workspace, &slack.token, name) {
+            pb.println(&format!("{}: Could not remove: {}", name, e));
+            failed += 1;
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message(&format!(
+        "Done! {} removed, {} failed",
+        names.len() - failed,
+        failed
+    ));
+
+    Ok(Summary::failed(failed))
+}
@@ -0,0 +1,236 @@
+//! `diff`
+
+use crate::*;
+
+#[derive(serde::Serialize, Debug)]
+struct WorkspaceDiff {
+    only_in_a: Vec<String>,
+    only_in_b: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    different: Option<Vec<String>>,
+}
+
+/// Splits emoji names into the ones only in `a`, only in `b`, and the ones in both
+pub(crate) fn diff_names<'a>(
+    a: &'a [Emoji],
+    b: &'a [Emoji],
+) -> (Vec<String>, Vec<String>, Vec<(&'a Emoji, &'a Emoji)>) {
+    let a_by_name: std::collections::BTreeMap<&str, &Emoji> =
+        a.iter().map(|e| (e.name.as_str(), e)).collect();
+    let b_by_name: std::collections::BTreeMap<&str, &Emoji> =
+        b.iter().map(|e| (e.name.as_str(), e)).collect();
+
+    let only_in_a = a_by_name
+        .keys()
+        .filter(|name| !b_by_name.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+    let only_in_b = b_by_name
+        .keys()
+        .filter(|name| !a_by_name.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+    let in_both = a_by_name
+        .iter()
+        .filter_map(|(name, ea)| b_by_name.get(name).map(|eb| (*ea, *eb)))
+        .collect();
+    (only_in_a, only_in_b, in_both)
+}
+
+#[derive(serde::Serialize, Debug)]
+struct LocalDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+impl LocalDiff {
+    /// Compares local metadata with the current state of the workspace
+    pub fn new(local: &[Emoji], remote: &[Emoji]) -> LocalDiff {
+        let (removed, added, in_both) = diff_names(local, remote);
+        let changed = in_both
+            .into_iter()
+            .filter(|(l, r)| l.url != r.url || l.avatar_hash != r.avatar_hash)
+            .map(|(l, _)| l.name.clone())
+            .collect();
+        LocalDiff {
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    /// How many emoji differ
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+}
+
+/// Prints a titled list of emoji names
+fn print_section(title: &str, names: &[String]) {
+    println!("{} ({}):", title, names.len());
+    for name in names {
+        println!("  {}", name);
+    }
+}
+
+pub(crate) fn print_json<T: serde::Serialize>(value: &T) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| Error::Serialization("Could not serialize".into(), e))?;
+    println!("{}", json);
+    Ok(())
+}
+
+pub(crate) fn diff(
+    client: &RetryingClient,
+    diff_opts: DiffOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = diff_opts.global + global_opts;
+
+    let get = |workspace: &str, token: &str| {
+        get_emoji(client, workspace, token)
+            .map_err(|e| Error::Api(format!("Could not get emojis from {}", workspace), e))
+    };
+
+    if let Some(path) = &diff_opts.path {
+        let local: Vec<Emoji> = read_emoji_dir(path)
+            .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+            .collect();
+        // guaranteed by structopt when a path is given
+        let workspace = diff_opts.workspace.unwrap_or_default();
+        let token = workspace_token(
+            &workspace,
+            diff_opts.token.as_deref(),
+            diff_opts.token_file.as_deref(),
+            "token",
+        )
+        .map_err(Error::InvalidArguments)?;
+        let remote = get(&workspace, &token)?;
+
+        let result = LocalDiff::new(&local, &remote);
+        match diff_opts.output {
+            OutputFormat::Json => print_json(&result)?,
+            OutputFormat::Text => {
+                print_section(&format!("Added in {}", workspace), &result.added);
+                print_section(&format!("Removed from {}", workspace), &result.removed);
+                print_section("Changed", &result.changed);
+            }
+        }
+        // like diff(1), any difference exits with 1
+        return Ok(Summary::failed(result.len()));
+    }
+
+    let workspace_a = diff_opts.workspace_a.unwrap_or_default();
+    let workspace_b = diff_opts.workspace_b.unwrap_or_default();
+    check_token_files(&[
+        diff_opts.token_file_a.as_deref(),
+        diff_opts.token_file_b.as_deref(),
+    ])?;
+    let token_a = workspace_token(
+        &workspace_a,
+        diff_opts.token_a.as_deref(),
+        diff_opts.token_file_a.as_deref(),
+        "token-a",
+    );
+    let token_b = workspace_token(
+        &workspace_b,
+        diff_opts.token_b.as_deref(),
+        diff_opts.token_file_b.as_deref(),
+        "token-b",
+    );
+    let emoji_a = get(&workspace_a, &token_a.map_err(Error::InvalidArguments)?)?;
+    let emoji_b = get(&workspace_b, &token_b.map_err(Error::InvalidArguments)?)?;
+
+    let (only_in_a, only_in_b, in_both) = diff_names(&emoji_a, &emoji_b);
+    let mut result = WorkspaceDiff {
+        only_in_a,
+        only_in_b,
+        different: None,
+    };
+
+    let mut failed = 0;
+    if diff_opts.compare_images {
+        let pb = progress.start(in_both.len() as u64, PROGRESS_TEMPLATE);
+        let rate_limiter = RateLimiter::new(20.0); // 20 dls / s
+        let mut different = vec![];
+
+        for (a, b) in track(&pb, in_both.into_iter()) {
+            pb.set_message(&a.name);
+            let is_different = if a.is_alias != 0 || b.is_alias != 0 {
+                // aliases have no image of their own, compare what they point to
+                a.is_alias != b.is_alias || a.alias_for != b.alias_for
+            } else {
+                if global_opts.verbose {
+                    pb.println(&format!("Comparing {} and {}", a.url, b.url));
+                }
+                let images = download_image(client, &a.url).and_then(|image_a| {
+                    rate_limiter.wait();
+                    Ok(image_a != download_image(client, &b.url)?)
+                });
+                rate_limiter.wait();
+                match images {
+                    Ok(is_different) => is_different,
+                    Err(e) => {
+                        pb.println(&format!("{}: Could not compare images: {}", a.name, e));
+                        failed += 1;
+                        continue;
+                    }
+                }
+            };
+            if is_different {
+                different.push(a.name.clone());
+            }
+        }
+        pb.finish_and_clear();
+        result.different = Some(different);
+    }
+
+    match diff_opts.output {
+        OutputFormat::Json => print_json(&result)?,
+        OutputFormat::Text => {
+            print_section(&format!("Only in {}", workspace_a), &result.only_in_a);
+            print_section(&format!("Only in {}", workspace_b), &result.only_in_b);
+            if let Some(different) = &result.different {
+                print_section("Different images", different);
+            }
+        }
+    }
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let a = vec![Emoji::new("both"), Emoji::new("only-a")];
+        let b = vec![Emoji::new("only-b"), Emoji::new("both")];
+        let (only_in_a, only_in_b, in_both) = diff_names(&a, &b);
+        assert_eq!(only_in_a, vec!["only-a".to_string()]);
+        assert_eq!(only_in_b, vec!["only-b".to_string()]);
+        assert_eq!(in_both.len(), 1);
+        assert_eq!(in_both[0].0.name, "both");
+    }
+
+    #[test]
+    fn local() {
+        let mut changed = Emoji::new("changed");
+        let local = vec![
+            Emoji::new("removed"),
+            Emoji::new("changed"),
+            Emoji::new("same"),
+        ];
+        changed.avatar_hash = "0xcafe".into();
+        let remote = vec![Emoji::new("same"), changed, Emoji::new("added")];
+
+        let result = LocalDiff::new(&local, &remote);
+        assert_eq!(result.added, vec!["added".to_string()]);
+        assert_eq!(result.removed, vec!["removed".to_string()]);
+        assert_eq!(result.changed, vec!["changed".to_string()]);
+        assert_ne!(result.len(), 0);
+        assert_eq!(LocalDiff::new(&local, &local).len(), 0);
+    }
+}
@@ -0,0 +1,2674 @@
+//! `download`, the download directory, and its bookkeeping files
+
+use crate::*;
+use std::fs::{read, read_dir, remove_file, File, OpenOptions};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The file in a download directory mapping alias names to the emoji they point to
+const ALIASES_FILE: &str = "aliases.json";
+
+pub(crate) fn download(
+    client: &RetryingClient,
+    download_opts: DownloadOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = download_opts.global + global_opts;
+    if download_opts.format_string.is_some() && download_opts.output_format != OutputFormat::Json {
+        return Err(Error::InvalidArguments(
+            "--format-string replaces the events of --output-format json, so it needs that".into(),
+        ));
+    }
+
+    let input = download_opts.path;
+    let from_stdin = input.as_os_str() == "-";
+    // without --dest the input directory is also where the images go, so it can be created
+    if !from_stdin && !input.exists() && download_opts.dest.is_some() {
+        return Err(Error::Failed(format!(
+            "Specified path does not exist: {:?}",
+            input
+        )));
+    }
+    let is_manifest = from_stdin || input.is_file();
+    let base_path = match (download_opts.dest, is_manifest) {
+        (Some(dest), _) => dest,
+        (None, false) => input.clone(),
+        // nothing is written next to an archive, so any directory does
+        (None, true) if download_opts.archive.is_some() => PathBuf::from("."),
+        (None, true) => {
+            return Err(Error::InvalidArguments(
+                "Use --dest to choose where to download the images to".into(),
+            ))
+        }
+    };
+    let may_create =
+        !download_opts.no_create && !download_opts.dry_run && !download_opts.verify_only;
+    if !base_path.exists() && may_create {
+        log::debug!("Creating {:?}", base_path);
+        std::fs::create_dir_all(&base_path)
+            .map_err(|e| Error::Io(format!("Could not create {:?}", base_path), e))?;
+    } else if !base_path.exists() {
+        return Err(Error::Failed(format!(
+            "Specified path does not exist: {:?}",
+            base_path
+        )));
+    } else if !base_path.is_dir() {
+        return Err(Error::Failed(format!(
+            "Specified path is not a directory: {:?}",
+            base_path
+        )));
+    }
+
+    if download_opts.verify_only {
+        let report = verify_checksums(&base_path)
+            .map_err(|e| Error::Io(format!("Could not verify {}", CHECKSUMS_FILE), e))?;
+        for file in &report.missing {
+            println!("missing: {}", file);
+        }
+        for file in &report.changed {
+            println!("changed: {}", file);
+        }
+        log::debug!("{} images match", report.matching);
+        return Ok(Summary::failed(report.missing.len() + report.changed.len()));
+    }
+
+    // one manifest is much faster to read than a file per emoji
+    let manifest = if is_manifest {
+        input.clone()
+    } else {
+        input.join(MANIFEST_FILE)
+    };
+    let all_emoji: Vec<Emoji> = if is_manifest || manifest_is_current(&input) {
+        let mut emoji = read_to_string(&manifest)
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse_emoji_manifest(&content).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                Error::InvalidArguments(format!("Could not read emoji from {:?}: {}", manifest, e))
+            })?;
+        assign_file_names(&mut emoji);
+        emoji
+    } else {
+        read_emoji_dir(&input)
+            .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+            .collect()
+    };
+    // aliases have no image of their own, their URL is just `alias:<target>`
+    let (aliases, emoji): (Vec<Emoji>, Vec<Emoji>) = all_emoji
+        .into_iter()
+        .partition(|e| e.is_alias != 0 || e.url.starts_with("alias:"));
+
+    let names_from_stdin = download_opts.stdin
+        || download_opts
+            .names_from
+            .as_ref()
+            .is_some_and(|path| path.as_os_str() == "-");
+    if from_stdin && names_from_stdin {
+        return Err(Error::InvalidArguments(
+            "Can't read both the emoji and their names from STDIN".into(),
+        ));
+    }
+    let names_from = match (&download_opts.names_from, download_opts.stdin) {
+        (Some(path), _) if path != std::path::Path::new("-") => Some(std::fs::read_to_string(path)),
+        (Some(_), _) | (None, true) => {
+            let mut names = String::new();
+            Some(std::io::Read::read_to_string(&mut std::io::stdin(), &mut names).map(|_| names))
+        }
+        (None, false) => None,
+    };
+    let listed_names = match names_from {
+        Some(Ok(content)) => parse_name_list(&content),
+        Some(Err(e)) => {
+            return Err(Error::Io(
+                "Could not read the emoji names to download".into(),
+                e,
+            ))
+        }
+        None => vec![],
+    };
+    let mut names = download_opts.names.clone();
+    names.extend(listed_names.iter().cloned());
+
+    let (selected, unknown_names) = select_emoji(
+        &emoji,
+        &aliases,
+        &names,
+        download_opts.emoji_filter.filter.as_ref(),
+    );
+    let name_filter = &download_opts.name_filter;
+    let emoji_filter = &download_opts.emoji_filter;
+    let (mut selected, filtered): (Vec<&Emoji>, Vec<&Emoji>) = selected
+        .into_iter()
+        .partition(|e| name_filter.matches(&e.name) && emoji_filter.matches_creator_and_date(e));
+    let state = DownloadState::read(&base_path);
+    if download_opts.since_last && !download_opts.force {
+        let before = selected.len();
+        selected.retain(|e| e.created > state.last_created);
+        log::debug!(
+            "Skipping {} emoji that the last download already got",
+            before - selected.len()
+        );
+    }
+    let layout = match (download_opts.by_user, download_opts.by_year) {
+        (true, _) => Layout::ByUser,
+        (_, true) => Layout::ByYear,
+        _ => Layout::Flat,
+    };
+    let emoji_path_pairs: Vec<(&Emoji, PathBuf)> = selected
+        .iter()
+        .map(|e| (*e, layout.image_path(&base_path, e)))
+        .collect();
+    let mut settings = DownloadSettings {
+        force: download_opts.force,
+        validate: download_opts.validate,
+        concurrency: download_opts.concurrency,
+        rate: download_opts.rate,
+        link_objects: download_opts.cas_links,
+        expected_bytes: None,
+        retries: download_opts.retries,
+        output_format: download_opts.output_format,
+        template: download_opts.format_string,
+    };
+
+    if let Some(path) = &download_opts.archive {
+        let archive = open_archive(path, &aliases, &settings)?;
+        return download_into_archive(
+            client,
+            archive,
+            &selected,
+            layout,
+            &settings,
+            &global_opts,
+            progress,
+        );
+    }
+
+    let plan = plan_download(
+        &selected,
+        &base_path,
+        layout,
+        download_opts.cas,
+        settings.force,
+    );
+    if download_opts.dry_run {
+        for (e, path) in &plan.pending {
+            println!("{} -> {}", e.url, path.display());
+        }
+        println!(
+            "{} to download, {} already present, {} filtered out",
+            plan.pending.len(),
+            plan.present,
+            filtered.len()
+        );
+        return Ok(Summary::done());
+    }
+    let pending: Vec<&str> = plan.pending.iter().map(|(e, _)| e.url.as_str()).collect();
+
+    log::debug!(
+        "Skipping {} aliases, writing them to {}",
+        aliases.len(),
+        ALIASES_FILE
+    );
+    if let Err(e) = write_aliases_file(&base_path, &aliases) {
+        log::error!("Could not write {}: {}", ALIASES_FILE, e);
+    }
+
+    if download_opts.estimate || download_opts.with_estimate {
+        let pb = progress.start(pending.len() as u64, PROGRESS_TEMPLATE);
+        pb.set_message("Estimating size");
+        let estimate = estimate_download_size(client, &pending, settings.rate, &pb);
+        pb.finish_and_clear();
+        if download_opts.estimate {
+            println!("{}", estimate);
+            return Ok(Summary::done());
+        }
+        log::info!("{}", estimate);
+        settings.expected_bytes = Some(estimate.bytes);
+    }
+
+    if !download_opts.ignore_disk_space {
+        let needed = settings
+            .expected_bytes
+            .unwrap_or(pending.len() as u64 * MAX_EMOJI_BYTES);
+        if let Some(available) = available_space(&base_path).filter(|a| *a < needed) {
+            return Err(Error::InvalidArguments(format!(
+                "The download needs up to {}, but only {} are available in {:?}\n\
+                 Use --with-estimate for a better guess, or --ignore-disk-space to try anyway",
+                indicatif::HumanBytes(needed),
+                indicatif::HumanBytes(available),
+                base_path
+            )));
+        }
+    }
+
+    for dir in emoji_path_pairs
+        .iter()
+        .filter_map(|(_, path)| path.parent())
+    {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Error::Io(format!("Could not create directory {:?}", dir), e))?;
+    }
+
+    handle_interrupts();
+    // the prefix shows how much was transferred
+    let pb = progress.start(
+        emoji_path_pairs.len() as u64,
+        "{wide_bar} {pos}/{len:.dim} {prefix} [{eta} left] {msg:<25!}",
+    );
+    let summary = if download_opts.cas {
+        download_objects(client, &selected, &base_path, &settings, &global_opts, &pb)
+    } else {
+        download_images(client, &emoji_path_pairs, &settings, &global_opts, &pb)
+    };
+    pb.finish_with_message(&format!(
+        "Done! {}, {} filtered out, {} retries",
+        summary,
+        filtered.len(),
+        client.retries()
+    ));
+    if interrupted() {
+        log::info!("Interrupted with {}, run again to continue", summary);
+        return Err(Error::Interrupted);
+    }
+    if summary.disk_full {
+        return Err(Error::Failed(format!(
+            "Stopped because there is no space left in {:?}",
+            base_path
+        )));
+    }
+    // the lines of a template are all that's printed, to keep them easy to process
+    if settings.template.is_none() {
+        settings.output_format.emit(&Event::Summary {
+            command: "download",
+            total: summary.downloaded + summary.skipped + summary.failed,
+            ok: summary.downloaded,
+            skipped: summary.skipped,
+            failed: summary.failed,
+            bytes: Some(summary.bytes),
+        });
+    }
+
+    if download_opts.link_aliases {
+        let dangling = link_aliases(&base_path, layout, &aliases, &emoji);
+        if dangling > 0 {
+            log::warn!("{} aliases point to emoji without an image", dangling);
+        }
+    }
+
+    if download_opts.since_last {
+        // only what's really on disk counts, failed images have to be tried again next time
+        let missing = plan_download(&selected, &base_path, layout, false, false).pending;
+        let missing: Vec<&Emoji> = missing.into_iter().map(|(e, _)| e).collect();
+        let last_created = downloaded_until(&selected, &missing).unwrap_or_default();
+        if last_created > state.last_created || download_opts.force {
+            let new_state = DownloadState { last_created };
+            if let Err(e) = new_state.write(&base_path) {
+                log::error!("Could not write {}: {}", STATE_FILE, e);
+            }
+        }
+    }
+
+    if download_opts.clean {
+        let orphans = orphan_images(&base_path)
+            .map_err(|e| Error::Io("Could not look for orphaned images".into(), e))?;
+        for image in &orphans {
+            if !download_opts.delete {
+                println!("{}", image.display());
+                continue;
+            }
+            log::debug!("Removing {:?}", image);
+            if let Err(e) = remove_file(image) {
+                log::error!("Could not remove {:?}: {}", image, e);
+            }
+            remove_file(CacheValidators::path(image)).ok();
+        }
+    }
+
+    if download_opts.checksums {
+        if let Err(e) = update_checksums(&base_path, &summary.written) {
+            return Err(Error::Failed(format!(
+                "Could not update {}: {}",
+                CHECKSUMS_FILE, e
+            )));
+        }
+    }
+
+    if !unknown_names.is_empty() {
+        let message = format!("No emoji found for: {}", unknown_names.join(", "));
+        if unknown_names.iter().any(|n| listed_names.contains(n)) {
+            return Err(Error::Failed(message));
+        }
+        log::warn!("{}", message);
+    }
+
+    Ok(Summary {
+        failed: summary.failed,
+        allowed_failures: download_opts.max_failures,
+    })
+}
+
+/// The file in a `list` output directory with the JSON of all emoji in one array
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+/// The file in a download directory remembering the newest emoji `--since-last` downloaded
+const STATE_FILE: &str = ".slack-emoji-state.json";
+
+/// JSON files in a download directory that aren't the metadata of an emoji
+pub(crate) const BOOKKEEPING_FILES: [&str; 4] =
+    [ALIASES_FILE, NAMES_FILE, MANIFEST_FILE, STATE_FILE];
+
+/// What `--since-last` remembers between downloads
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, PartialEq)]
+struct DownloadState {
+    /// All emoji created up to then are downloaded
+    last_created: Timestamp,
+}
+
+impl DownloadState {
+    /// Reads the state of a directory, starting over if there is none or it's unreadable
+    pub fn read(dir: &std::path::Path) -> DownloadState {
+        read(dir.join(STATE_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, dir: &std::path::Path) -> std::io::Result<()> {
+        let serialized = serde_json::to_string_pretty(self)?;
+        write_atomically(&dir.join(STATE_FILE), (serialized + "\n").as_bytes())
+    }
+}
+
+/// The newest creation time up to which all of the emoji are downloaded
+///
+/// `missing` are those without an image on disk. Everything created after the oldest of them
+/// has to be looked at again next time.
+fn downloaded_until(emoji: &[&Emoji], missing: &[&Emoji]) -> Option<Timestamp> {
+    let oldest_missing = missing.iter().map(|e| e.created).min();
+    emoji
+        .iter()
+        .map(|e| e.created)
+        .filter(|created| oldest_missing.is_none_or(|oldest| *created < oldest))
+        .max()
+}
+
+/// The directory of a download directory that `--cas` stores images in
+const OBJECTS_DIR: &str = "objects";
+/// The file in a download directory mapping emoji names to the hashes of their stored images
+pub(crate) const NAMES_FILE: &str = "names.json";
+
+/// Where an image with this SHA-256 hash is stored by `--cas`
+///
+/// `hash` has to pass `is_sha256_hex`, hashes read from metadata files could be anything.
+pub(crate) fn object_path(base_path: &std::path::Path, hash: &str) -> PathBuf {
+    debug_assert!(is_sha256_hex(hash));
+    base_path.join(OBJECTS_DIR).join(&hash[..2]).join(hash)
+}
+
+/// Downloads the images of emoji into a content-addressable store and updates the `NAMES_FILE`
+fn download_objects(
+    client: &RetryingClient,
+    emoji: &[&Emoji],
+    base_path: &std::path::Path,
+    settings: &DownloadSettings,
+    global_opts: &GlobalOptions,
+    pb: &dyn Progress,
+) -> DownloadSummary {
+    let rate_limiter = RateLimiter::new(settings.rate);
+    let progress = TransferProgress::new(pb, settings.expected_bytes);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let summary = std::sync::Mutex::new(DownloadSummary::default());
+    let hashes = std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            scope.spawn(|| {
+                while let Some(e) = emoji
+                    .get(next.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+                    .filter(|_| !interrupted() && !summary.lock().unwrap().disk_full)
+                {
+                    let (outcome, hash) = download_object(
+                        client,
+                        e,
+                        base_path,
+                        settings,
+                        &rate_limiter,
+                        global_opts,
+                        &progress,
+                    );
+                    if let Some(hash) = hash {
+                        hashes.lock().unwrap().insert(e.name.clone(), hash);
+                    }
+                    settings.report(e, &outcome);
+                    summary.lock().unwrap().count(outcome);
+                    pb.inc(1);
+                }
+            });
+        }
+    });
+
+    let names_path = base_path.join(NAMES_FILE);
+    let mut names = read_names_file(base_path);
+    names.extend(hashes.into_inner().unwrap());
+    let written = serde_json::to_string_pretty(&names)
+        .map_err(std::io::Error::from)
+        .and_then(|serialized| write_atomically(&names_path, (serialized + "\n").as_bytes()));
+    if let Err(e) = written {
+        pb.println(&format!("Could not write {}: {}", NAMES_FILE, e));
+    }
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.bytes = progress.bytes();
+    summary.retried = progress.retried();
+    summary
+}
+
+/// The hashes of the stored images by emoji name from the `NAMES_FILE`, empty if there is none
+pub(crate) fn read_names_file(
+    base_path: &std::path::Path,
+) -> std::collections::BTreeMap<String, String> {
+    read(base_path.join(NAMES_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Whether `hash` looks like the output of `sha256_hex`, and is safe to use in a path
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// The hash of an emoji's image if it's already in the content-addressable store
+fn stored_object<'a>(base_path: &std::path::Path, emoji: &'a Emoji) -> Option<&'a String> {
+    emoji
+        .image_sha256
+        .as_ref()
+        .filter(|hash| is_sha256_hex(hash) && object_path(base_path, hash).is_file())
+}
+
+/// Downloads the image of an emoji into the content-addressable store, unless it's already there
+///
+/// Returns the hash of the image if it is stored.
+fn download_object(
+    client: &RetryingClient,
+    emoji: &Emoji,
+    base_path: &std::path::Path,
+    settings: &DownloadSettings,
+    rate_limiter: &RateLimiter,
+    global_opts: &GlobalOptions,
+    pb: &TransferProgress,
+) -> (DownloadOutcome, Option<String>) {
+    let stored = stored_object(base_path, emoji);
+    if let (Some(hash), false) = (stored, settings.force) {
+        if settings.link_objects {
+            let object = object_path(base_path, hash);
+            let linked =
+                read(&object).and_then(|bytes| link_object(base_path, emoji, &object, &bytes));
+            if let Err(e) = linked {
+                pb.println(&format!(
+                    "{}: Could not link {:?}: {}",
+                    emoji.name, object, e
+                ));
+            }
+        }
+        return (DownloadOutcome::Skipped, Some(hash.clone()));
+    }
+
+    rate_limiter.wait();
+    pb.set_message(&emoji.name);
+    if global_opts.verbose {
+        pb.println(&format!("Downloading {}", emoji.url));
+    }
+    let bytes = match fetch_image_retrying(client, &emoji.url, None, settings.retries, pb) {
+        Ok(Some(image)) => image.bytes,
+        // a proxy or CDN that gets it wrong, there were no validators to be up to date with
+        Ok(None) => {
+            pb.println(&format!(
+                "{}: {} answered with Not Modified without being asked",
+                emoji.name, emoji.url
+            ));
+            return (DownloadOutcome::Failed, None);
+        }
+        Err(e) => {
+            pb.println(&format!(
+                "{}: Could not request {}: {}",
+                emoji.name, emoji.url, e
+            ));
+            return (DownloadOutcome::Failed, None);
+        }
+    };
+    let hash = sha256_hex(&bytes);
+    let object = object_path(base_path, &hash);
+    if !object.is_file() {
+        let written = object
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| write_atomically(&object, &bytes));
+        if let Err(e) = written {
+            pb.println(&format!("Could not write to {:?}: {}", object, e));
+            return (DownloadOutcome::write_failure(&e), None);
+        }
+    } else if global_opts.verbose {
+        pb.println(&format!("{}: Same image as {:?}", emoji.name, object));
+    }
+
+    if let Err(e) = record_image_digest(&image_path(base_path, emoji), &bytes) {
+        pb.println(&format!("Could not record hash of {}: {}", emoji.name, e));
+    }
+    if settings.link_objects {
+        if let Err(e) = link_object(base_path, emoji, &object, &bytes) {
+            pb.println(&format!(
+                "{}: Could not link {:?}: {}",
+                emoji.name, object, e
+            ));
+        }
+    }
+    (DownloadOutcome::Downloaded(object), Some(hash))
+}
+
+/// Hardlinks a stored image under the name of its emoji, replacing what's there
+fn link_object(
+    base_path: &std::path::Path,
+    emoji: &Emoji,
+    object: &std::path::Path,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    let link = base_path
+        .join(emoji.file_name())
+        .with_extension(downloaded_image_extension(bytes, None, &emoji.url));
+    if link.symlink_metadata().is_ok() {
+        remove_file(&link)?;
+    }
+    std::fs::hard_link(object, link)
+}
+
+/// Images in a directory without a JSON file of the same name
+///
+/// Symlinks are left out, they are links to aliases and not images of their own.
+fn orphan_images(dir: &std::path::Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut orphans = vec![];
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let is_image = path.extension().is_some_and(|extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+        });
+        if is_image && entry.file_type()?.is_file() && !metadata_path(&path).is_file() {
+            orphans.push(path);
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// What a download is going to fetch, worked out without any requests or writes
+struct DownloadPlan<'a> {
+    /// The images to download and where they go, the objects directory with `--cas`
+    pending: Vec<(&'a Emoji, PathBuf)>,
+    /// How many images are already downloaded
+    present: usize,
+}
+
+fn plan_download<'a>(
+    emoji: &[&'a Emoji],
+    base_path: &std::path::Path,
+    layout: Layout,
+    cas: bool,
+    force: bool,
+) -> DownloadPlan<'a> {
+    let mut plan = DownloadPlan {
+        pending: vec![],
+        present: 0,
+    };
+    for e in emoji {
+        let (path, present) = if cas {
+            let present = stored_object(base_path, e).is_some();
+            (base_path.join(OBJECTS_DIR), present)
+        } else {
+            let path = layout.image_path(base_path, e);
+            let present = is_complete_image(&path);
+            (path, present)
+        };
+        if present && !force {
+            plan.present += 1;
+        } else {
+            plan.pending.push((e, path));
+        }
+    }
+    plan
+}
+
+/// Parses emoji from a JSON array, or from JSON objects following each other
+pub(crate) fn parse_emoji_manifest(content: &str) -> serde_json::Result<Vec<Emoji>> {
+    if content.trim_start().starts_with('[') {
+        serde_json::from_str(content)
+    } else {
+        serde_json::Deserializer::from_str(content)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Reads emoji names from a list with one name or JSON file path per line
+fn parse_name_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| metadata_stem(std::path::Path::new(line)).unwrap_or_else(|| line.to_string()))
+        .collect()
+}
+
+/// Selects the emoji matching the requested names and filter, plus the names that matched nothing
+///
+/// Selecting an alias selects the emoji it points to, since aliases have no image of their own.
+/// Without names and filter everything is selected.
+fn select_emoji<'a>(
+    emoji: &'a [Emoji],
+    aliases: &[Emoji],
+    names: &[String],
+    filter: Option<&regex::Regex>,
+) -> (Vec<&'a Emoji>, Vec<String>) {
+    let is_selected = |name: &str| {
+        (names.is_empty() || names.iter().any(|n| n == name))
+            && filter.is_none_or(|f| f.is_match(name))
+    };
+    let selected = emoji
+        .iter()
+        .filter(|e| {
+            is_selected(&e.name)
+                || aliases
+                    .iter()
+                    .any(|a| a.alias_for == e.name && is_selected(&a.name))
+        })
+        .collect();
+    let unknown = names
+        .iter()
+        .filter(|n| !emoji.iter().chain(aliases).any(|e| &e.name == *n))
+        .cloned()
+        .collect();
+    (selected, unknown)
+}
+
+/// The manifest of image hashes in a download directory, in the format of `sha256sum`
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Reads the file name to hash mapping of a `sha256sum` style manifest
+fn parse_checksums(content: &str) -> std::collections::BTreeMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, file)| {
+            // a second space means text mode, a star binary mode
+            let file = file.strip_prefix([' ', '*']).unwrap_or(file);
+            (file.to_string(), hash.to_lowercase())
+        })
+        .collect()
+}
+
+/// Adds all images of a directory to its manifest, rehashing the `changed` ones
+///
+/// Images already in the manifest aren't read again, files that don't exist anymore are dropped.
+fn update_checksums(dir: &std::path::Path, changed: &[PathBuf]) -> std::io::Result<()> {
+    let manifest = dir.join(CHECKSUMS_FILE);
+    let mut checksums = if manifest.is_file() {
+        parse_checksums(&std::fs::read_to_string(&manifest)?)
+    } else {
+        Default::default()
+    };
+    checksums.retain(|file, _| dir.join(file).is_file());
+    for path in changed {
+        if let Some(file) = path.file_name() {
+            checksums.remove(&*file.to_string_lossy());
+        }
+    }
+    for (_, path) in read_image_dir(dir)? {
+        let file = match path.file_name() {
+            Some(file) => file.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if let std::collections::btree_map::Entry::Vacant(entry) = checksums.entry(file) {
+            entry.insert(sha256_hex(&read(&path)?));
+        }
+    }
+
+    let content: String = checksums
+        .iter()
+        .map(|(file, hash)| format!("{}  {}\n", hash, file))
+        .collect();
+    write_atomically(&manifest, content.as_bytes())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ChecksumReport {
+    matching: usize,
+    missing: Vec<String>,
+    changed: Vec<String>,
+}
+
+/// Compares the images of a directory with the hashes in its manifest
+fn verify_checksums(dir: &std::path::Path) -> std::io::Result<ChecksumReport> {
+    let checksums = parse_checksums(&std::fs::read_to_string(dir.join(CHECKSUMS_FILE))?);
+    let mut report = ChecksumReport::default();
+    for (file, hash) in checksums {
+        match read(dir.join(&file)) {
+            Ok(bytes) if sha256_hex(&bytes) == hash => report.matching += 1,
+            Ok(_) => report.changed.push(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => report.missing.push(file),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(report)
+}
+
+/// Creates an image file for each alias that links to the image of its target
+///
+/// Returns the number of aliases whose target has no downloaded image.
+fn link_aliases(
+    base_path: &std::path::Path,
+    layout: Layout,
+    aliases: &[Emoji],
+    emoji: &[Emoji],
+) -> usize {
+    let mut dangling = 0;
+    for alias in aliases {
+        let target = emoji
+            .iter()
+            .find(|e| e.name == alias.alias_for)
+            .map(|target| layout.image_path(base_path, target))
+            .filter(|path| path.is_file());
+        let target = match target {
+            Some(target) => target,
+            None => {
+                log::warn!(
+                    "{}: {} has no downloaded image to link to",
+                    alias.name,
+                    alias.alias_for
+                );
+                dangling += 1;
+                continue;
+            }
+        };
+        let link = target
+            .with_file_name(alias.file_name())
+            .with_extension(target.extension().unwrap_or_default());
+
+        if link.symlink_metadata().is_ok() {
+            remove_file(&link).ok(); // might point to an old target
+        }
+        match link_or_copy(&target, &link) {
+            Ok(false) => log::debug!("Linked {:?} to {:?}", link, target),
+            Ok(true) => log::debug!("Copied {:?} to {:?}", target, link),
+            Err(e) => log::error!("Could not link {:?} to {:?}: {}", link, target, e),
+        }
+    }
+    dangling
+}
+
+/// How images are arranged inside a download directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Layout {
+    /// All images directly in the directory
+    Flat,
+    /// In a subdirectory per user who added the emoji
+    ByUser,
+    /// In a subdirectory per year the emoji was added in
+    ByYear,
+}
+
+impl Layout {
+    /// Where the image of an emoji is stored with this layout, see `image_path`
+    pub fn image_path(&self, base_path: &std::path::Path, emoji: &Emoji) -> PathBuf {
+        image_path(&self.dir(base_path, emoji), emoji)
+    }
+
+    /// The directory the image of an emoji goes in
+    pub fn dir(&self, base_path: &std::path::Path, emoji: &Emoji) -> PathBuf {
+        match self {
+            Layout::Flat => base_path.to_path_buf(),
+            Layout::ByUser => base_path.join(sanitize_directory_name(&emoji.user_display_name)),
+            Layout::ByYear => base_path.join(emoji.created.date().0.to_string()),
+        }
+    }
+}
+
+/// The image of an emoji in a download directory, no matter how `download` arranged it
+///
+/// Looks directly in the directory, then in the subdirectories of --by-user and --by-year, and
+/// last in the store of --cas, with `names` from its `NAMES_FILE`.
+pub(crate) fn find_image(
+    base_path: &std::path::Path,
+    emoji: &Emoji,
+    names: &std::collections::BTreeMap<String, String>,
+) -> Option<PathBuf> {
+    [Layout::Flat, Layout::ByUser, Layout::ByYear]
+        .iter()
+        .map(|layout| layout.image_path(base_path, emoji))
+        .find(|path| path.is_file())
+        .or_else(|| {
+            names
+                .get(&emoji.name)
+                .or(emoji.image_sha256.as_ref())
+                .filter(|hash| is_sha256_hex(hash))
+                .map(|hash| object_path(base_path, hash))
+                .filter(|path| path.is_file())
+        })
+}
+
+/// Gives emoji whose file names only differ in case distinct file names
+///
+/// Case-insensitive file systems would store them in the same files otherwise. The oldest emoji
+/// keeps its file name, newer ones get a `~1`, `~2`, ... suffix, no matter the order they're in.
+pub(crate) fn assign_file_names(emoji: &mut [Emoji]) {
+    let mut taken: std::collections::HashMap<String, String> = Default::default();
+    let mut by_age: Vec<&mut Emoji> = emoji.iter_mut().collect();
+    by_age.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+    for e in by_age {
+        let file_name = e.file_name();
+        let mut unique = file_name.clone();
+        let mut suffix = 0;
+        while taken.contains_key(&unique.to_lowercase()) {
+            suffix += 1;
+            unique = format!("{}~{}", file_name, suffix);
+        }
+        if unique != file_name {
+            log::warn!(
+                "{} and {} only differ in case, storing {} as {}",
+                taken[&file_name.to_lowercase()],
+                e.name,
+                e.name,
+                unique
+            );
+            e.file_name = Some(unique.clone());
+        }
+        taken.insert(unique.to_lowercase(), e.name.clone());
+    }
+}
+
+/// Makes a name usable as a single directory name on all platforms
+fn sanitize_directory_name(name: &str) -> String {
+    let sanitized = replace_reserved_characters(name);
+    // Windows drops trailing dots and spaces, and `.` or `..` would leave the directory
+    let sanitized = sanitized.trim_end_matches(['.', ' ']).trim_start();
+    if sanitized.is_empty() {
+        "unknown".to_string()
+    } else {
+        sanitized.to_string()
+    }
+}
+
+/// Symlinks `link` to `target` in the same directory, copying it where symlinks fail
+///
+/// Returns whether the file had to be copied.
+fn link_or_copy(target: &std::path::Path, link: &std::path::Path) -> std::io::Result<bool> {
+    // relative, so the directory can be moved
+    let relative = target.file_name().unwrap_or_default();
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(relative, link);
+    #[cfg(windows)]
+    let linked = std::os::windows::fs::symlink_file(relative, link);
+    #[cfg(not(any(unix, windows)))]
+    let linked: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+
+    match linked {
+        Ok(()) => Ok(false),
+        Err(_) => std::fs::copy(target, link).map(|_| true),
+    }
+}
+
+/// Whether a directory has a `MANIFEST_FILE` that is at least as new as all other metadata
+///
+/// The per-emoji files are newer after a `list` without `--manifest`, or after `download`
+/// recorded the hashes of images in them.
+pub(crate) fn manifest_is_current(dir: &std::path::Path) -> bool {
+    let modified = |path: &std::path::Path| path.metadata().and_then(|m| m.modified()).ok();
+    let manifest = match modified(&dir.join(MANIFEST_FILE)) {
+        Some(manifest) => manifest,
+        None => return false,
+    };
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !BOOKKEEPING_FILES.contains(&&*entry.file_name().to_string_lossy()))
+        .filter(|entry| metadata_stem(&entry.path()).is_some())
+        .all(|entry| modified(&entry.path()).is_some_and(|json| json <= manifest))
+}
+
+/// Writes all emoji in the order given into the `MANIFEST_FILE` of a directory
+pub(crate) fn write_manifest(dir: &std::path::Path, emoji: &[Emoji]) -> std::io::Result<()> {
+    let serialized = serde_json::to_string_pretty(emoji)?;
+    write_atomically(&dir.join(MANIFEST_FILE), (serialized + "\n").as_bytes())
+}
+
+/// Writes the alias to target mapping into the `ALIASES_FILE` of a directory
+fn write_aliases_file(base_path: &std::path::Path, aliases: &[Emoji]) -> std::io::Result<()> {
+    std::fs::write(base_path.join(ALIASES_FILE), aliases_json(aliases)?)
+}
+
+/// The content of the `ALIASES_FILE`
+fn aliases_json(aliases: &[Emoji]) -> serde_json::Result<String> {
+    let map: std::collections::BTreeMap<&str, &str> = aliases
+        .iter()
+        .map(|e| (e.name.as_str(), e.alias_for.as_str()))
+        .collect();
+    Ok(serde_json::to_string_pretty(&map)? + "\n")
+}
+
+/// Where the image of an emoji is stored inside a download directory
+///
+/// Prefers an already downloaded image, otherwise guesses the extension from the URL.
+pub(crate) fn image_path(base_path: &std::path::Path, emoji: &Emoji) -> PathBuf {
+    let path = base_path.join(emoji.file_name());
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|extension| path.with_extension(extension))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| path.with_extension(url_extension(&emoji.url)))
+}
+
+/// The image extension at the end of an URL's path, `png` if there is none
+fn url_extension(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            std::path::Path::new(url.path())
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+        })
+        .filter(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+        .unwrap_or_else(|| "png".to_string())
+}
+
+/// The file extension for a downloaded image
+///
+/// Detected from the content first, then from the `Content-Type`, then from the URL.
+fn downloaded_image_extension(bytes: &[u8], content_type: Option<&str>, url: &str) -> String {
+    let from_content_type = content_type.and_then(|content_type| {
+        match content_type.split(';').next().unwrap_or_default().trim() {
+            "image/png" => Some("png"),
+            "image/gif" => Some("gif"),
+            "image/jpeg" => Some("jpg"),
+            "image/webp" => Some("webp"),
+            _ => None,
+        }
+    });
+    image_format(bytes)
+        .or(from_content_type)
+        .map(String::from)
+        .unwrap_or_else(|| url_extension(url))
+}
+
+/// What happened to a single image in `download_images`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DownloadOutcome {
+    /// Written to the path
+    Downloaded(PathBuf),
+    Skipped,
+    Failed,
+    /// Failed because there's no space left, so all others would fail too
+    DiskFull,
+}
+
+impl DownloadOutcome {
+    /// The event reporting this outcome for `--output-format json`
+    pub fn event<'a>(&'a self, name: &'a str) -> Event<'a> {
+        let (status, path) = match self {
+            DownloadOutcome::Downloaded(path) => (EventStatus::Ok, Some(path.as_path())),
+            DownloadOutcome::Skipped => (EventStatus::Skipped, None),
+            DownloadOutcome::Failed | DownloadOutcome::DiskFull => (EventStatus::Failed, None),
+        };
+        Event::Download {
+            name,
+            status,
+            path,
+            bytes: path.and_then(|path| path.metadata().ok()).map(|m| m.len()),
+        }
+    }
+
+    /// The outcome of failing to write an image
+    pub fn write_failure(error: &std::io::Error) -> DownloadOutcome {
+        if error.kind() == std::io::ErrorKind::StorageFull {
+            DownloadOutcome::DiskFull
+        } else {
+            DownloadOutcome::Failed
+        }
+    }
+}
+
+/// How many images `download_images` downloaded, skipped because they exist, or failed on
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct DownloadSummary {
+    downloaded: usize,
+    skipped: usize,
+    pub(crate) failed: usize,
+    /// Where the downloaded images were written to
+    written: Vec<PathBuf>,
+    /// How many bytes were transferred
+    bytes: u64,
+    /// How many images needed more than one attempt
+    retried: usize,
+    /// Whether the download stopped early because the disk is full
+    disk_full: bool,
+}
+
+impl DownloadSummary {
+    pub fn count(&mut self, outcome: DownloadOutcome) {
+        match outcome {
+            DownloadOutcome::Downloaded(path) => {
+                self.downloaded += 1;
+                self.written.push(path);
+            }
+            DownloadOutcome::Skipped => self.skipped += 1,
+            DownloadOutcome::Failed => self.failed += 1,
+            DownloadOutcome::DiskFull => {
+                self.failed += 1;
+                self.disk_full = true;
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} downloaded ({}), {} already present, {} failed",
+            self.downloaded,
+            indicatif::HumanBytes(self.bytes),
+            self.skipped,
+            self.failed
+        )?;
+        if self.retried > 0 {
+            write!(f, ", {} needed retries", self.retried)?;
+        }
+        Ok(())
+    }
+}
+
+/// How `download_images` and `download_objects` fetch images
+#[derive(Debug, Clone)]
+pub(crate) struct DownloadSettings {
+    /// Download images again even if they were downloaded before
+    pub(crate) force: bool,
+    /// Compare the size of downloaded images with what Slack reports before skipping them
+    pub(crate) validate: bool,
+    /// How many images to download at the same time
+    pub(crate) concurrency: usize,
+    /// How many images to download per second at most, 0 for unlimited
+    pub(crate) rate: f64,
+    /// Hardlink images stored by `download_objects` under the name of their emoji
+    pub(crate) link_objects: bool,
+    /// How many bytes the download is expected to transfer
+    pub(crate) expected_bytes: Option<u64>,
+    /// How often to try again to download an image after errors that might go away
+    pub(crate) retries: u32,
+    /// Whether to print an event for each image
+    pub(crate) output_format: OutputFormat,
+    /// What to print for each image instead of its JSON event
+    pub(crate) template: Option<Template>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> DownloadSettings {
+        DownloadSettings {
+            force: false,
+            validate: false,
+            concurrency: 1,
+            rate: 20.0,
+            link_objects: false,
+            expected_bytes: None,
+            retries: 2,
+            output_format: OutputFormat::Text,
+            template: None,
+        }
+    }
+}
+
+impl DownloadSettings {
+    /// Prints the event of an image, as a line of the template if there is one
+    fn report(&self, e: &Emoji, outcome: &DownloadOutcome) {
+        let event = outcome.event(&e.name);
+        match &self.template {
+            Some(template) if self.output_format == OutputFormat::Json => {
+                println!("{}", template.render(e, Some(&event)))
+            }
+            _ => self.output_format.emit(&event),
+        }
+    }
+}
+
+/// Downloads `(emoji, path)` pairs, skipping already downloaded files unless forced
+///
+/// Runs `concurrency` downloads at once, all of them sharing one rate limit.
+pub(crate) fn download_images(
+    client: &RetryingClient,
+    emoji_path_pairs: &[(&Emoji, PathBuf)],
+    settings: &DownloadSettings,
+    global_opts: &GlobalOptions,
+    pb: &dyn Progress,
+) -> DownloadSummary {
+    let dirs: std::collections::BTreeSet<&std::path::Path> = emoji_path_pairs
+        .iter()
+        .filter_map(|(_, path)| path.parent())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    for dir in dirs {
+        if let Err(e) = remove_partial_files(dir) {
+            pb.println(&format!("Could not remove interrupted downloads: {}", e));
+        }
+    }
+
+    let rate_limiter = RateLimiter::new(settings.rate);
+    let progress = TransferProgress::new(pb, settings.expected_bytes);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let summary = std::sync::Mutex::new(DownloadSummary::default());
+
+    std::thread::scope(|scope| {
+        for _ in 0..settings.concurrency.max(1) {
+            scope.spawn(|| {
+                while let Some((emoji, path)) = emoji_path_pairs
+                    .get(next.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+                    .filter(|_| !interrupted() && !summary.lock().unwrap().disk_full)
+                {
+                    let outcome = download_image_to(
+                        client,
+                        emoji,
+                        path,
+                        settings,
+                        &rate_limiter,
+                        global_opts,
+                        &progress,
+                    );
+                    settings.report(emoji, &outcome);
+                    summary.lock().unwrap().count(outcome);
+                    pb.inc(1);
+                }
+            });
+        }
+    });
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.bytes = progress.bytes();
+    summary.retried = progress.retried();
+    summary
+}
+
+/// Whether a file holds a complete image that doesn't need to be downloaded again
+///
+/// Empty files and files that aren't images are incomplete. With `validate` their size also has
+/// to match the `Content-Length` Slack reports for the image.
+fn is_downloaded(
+    client: &RetryingClient,
+    url: &str,
+    path: &std::path::Path,
+    validate: bool,
+    rate_limiter: &RateLimiter,
+) -> bool {
+    if !is_complete_image(path) {
+        return false;
+    }
+    if !validate {
+        return true;
+    }
+    let size = match path.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return false,
+    };
+
+    rate_limiter.wait();
+    // if Slack doesn't tell, there's nothing to compare with
+    image_size(client, url).is_none_or(|length| length == size)
+}
+
+/// The largest image Slack accepts for a custom emoji, also the guess when the real size is unknown
+pub(crate) const MAX_EMOJI_BYTES: u64 = 128 * 1024;
+
+/// How many bytes can still be written to the file system a path is on, if that can be found out
+#[cfg(unix)]
+fn available_space(path: &std::path::Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is NUL terminated and `stat` is a valid statvfs to write to
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &std::path::Path) -> Option<u64> {
+    None
+}
+
+/// Whether a file is a non-empty image, judging by its first bytes
+pub(crate) fn is_complete_image(path: &std::path::Path) -> bool {
+    let size = match path.metadata() {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => return false,
+    };
+    let mut magic = vec![];
+    let is_image = File::open(path)
+        .and_then(|file| std::io::Read::read_to_end(&mut std::io::Read::take(file, 12), &mut magic))
+        .map(|_| image_format(&magic).is_some())
+        .unwrap_or(false);
+    size > 0 && is_image
+}
+
+/// The `Content-Length` Slack reports for an image, without downloading it
+fn image_size(client: &RetryingClient, url: &str) -> Option<u64> {
+    client
+        .execute_with_retries("", || {
+            client.head(url).timeout(client.image_timeout).build()
+        })
+        .and_then(|res| res.error_for_status())
+        .ok()
+        .and_then(|res| {
+            res.headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+        })
+}
+
+/// How much a download will transfer, according to the sizes Slack reports
+#[derive(Debug, Default, PartialEq)]
+struct SizeEstimate {
+    files: usize,
+    bytes: u64,
+    /// Files Slack didn't report a size for, not included in `bytes`
+    unknown: usize,
+}
+
+impl std::fmt::Display for SizeEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} images to download, {} in total",
+            self.files,
+            indicatif::HumanBytes(self.bytes)
+        )?;
+        if self.unknown > 0 {
+            write!(f, ", {} of unknown size", self.unknown)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds up the sizes of images with HEAD requests
+fn estimate_download_size(
+    client: &RetryingClient,
+    urls: &[&str],
+    rate: f64,
+    pb: &dyn Progress,
+) -> SizeEstimate {
+    let rate_limiter = RateLimiter::new(rate);
+    let mut estimate = SizeEstimate::default();
+    for url in track(pb, urls.iter()) {
+        rate_limiter.wait();
+        estimate.files += 1;
+        match image_size(client, url) {
+            Some(bytes) => estimate.bytes += bytes,
+            None => estimate.unknown += 1,
+        }
+    }
+    estimate
+}
+
+/// Downloads the image of an emoji into a file, reporting failures on the progress bar
+fn download_image_to(
+    client: &RetryingClient,
+    emoji: &Emoji,
+    path: &std::path::Path,
+    settings: &DownloadSettings,
+    rate_limiter: &RateLimiter,
+    global_opts: &GlobalOptions,
+    pb: &TransferProgress,
+) -> DownloadOutcome {
+    let url = emoji.url.as_str();
+    // a forced run only needs to fetch images that changed since the last download
+    let mut validators = None;
+    if path.is_file() {
+        if !is_downloaded(client, url, path, settings.validate, rate_limiter) {
+            if global_opts.verbose {
+                pb.println(&format!("{:?} is incomplete, downloading again", path));
+            }
+        } else if !settings.force {
+            record_missing_image_digest(path, pb.pb);
+            return DownloadOutcome::Skipped; // skip downloaded files
+        } else {
+            validators = Some(CacheValidators::read(path));
+        }
+    }
+    rate_limiter.wait();
+    pb.set_message(&path.to_string_lossy());
+    if global_opts.verbose {
+        pb.println(&format!("Downloading {}", url));
+    }
+
+    let image = match fetch_image_retrying(client, url, validators.as_ref(), settings.retries, pb) {
+        Ok(Some(image)) => image,
+        Ok(None) => {
+            if global_opts.verbose {
+                pb.println(&format!("{:?} is up to date", path));
+            }
+            record_missing_image_digest(path, pb.pb);
+            return DownloadOutcome::Skipped;
+        }
+        Err(e) => {
+            pb.println(&format!(
+                "Could not request {:?}: {}",
+                path,
+                client.describe_error(e)
+            ));
+            return DownloadOutcome::Failed;
+        }
+    };
+    let bytes = image.bytes;
+    let target = path.with_extension(downloaded_image_extension(
+        &bytes,
+        image.content_type.as_deref(),
+        url,
+    ));
+    if let Err(e) = image.validators.write(&target) {
+        pb.println(&format!("Could not write ETag of {:?}: {}", target, e));
+    }
+
+    match write_atomically(&target, &bytes) {
+        Ok(_) => {
+            // an earlier download guessed the wrong extension
+            if target != path && path.is_file() {
+                if global_opts.verbose {
+                    pb.println(&format!("Replacing {:?} with {:?}", path, target));
+                }
+                remove_file(path).ok();
+            }
+            if let Err(e) = record_image_digest(&target, &bytes) {
+                pb.println(&format!("Could not record hash of {:?}: {}", target, e));
+            }
+            if let Err(e) = set_created_time(&target, emoji) {
+                if global_opts.verbose {
+                    pb.println(&format!(
+                        "Could not set modification time of {:?}: {}",
+                        target, e
+                    ));
+                }
+            }
+            DownloadOutcome::Downloaded(target)
+        }
+        Err(e) => {
+            pb.println(&format!("Could not write to {:?}: {}", target, e));
+            DownloadOutcome::write_failure(&e)
+        }
+    }
+}
+
+/// Creates the archive of `download --archive`, starting with the `ALIASES_FILE`
+pub(crate) fn open_archive(
+    path: &std::path::Path,
+    aliases: &[Emoji],
+    settings: &DownloadSettings,
+) -> Result<ArchiveWriter, Error> {
+    let format = ArchiveFormat::of(path).ok_or_else(|| {
+        Error::InvalidArguments(format!(
+            "Can't tell the archive format of {:?}, use .tar, .tar.gz, .tgz, or .zip",
+            path
+        ))
+    })?;
+    let to_stdout = path.as_os_str() == "-";
+    if to_stdout && settings.output_format == OutputFormat::Json {
+        return Err(Error::InvalidArguments(
+            "--output-format json prints events to STDOUT, so --archive can't be '-'".into(),
+        ));
+    }
+    if !to_stdout && path.exists() && !settings.force {
+        return Err(Error::InvalidArguments(format!(
+            "{:?} already exists, use --force to replace it",
+            path
+        )));
+    }
+    ArchiveWriter::create(path, format)
+        .and_then(|mut archive| {
+            let aliases = aliases_json(aliases)?;
+            archive.add(ALIASES_FILE, aliases.as_bytes(), Timestamp::now())?;
+            Ok(archive)
+        })
+        .map_err(|e| Error::Io(format!("Could not create {:?}", path), e))
+}
+
+/// Downloads images straight into an archive for `download --archive`
+pub(crate) fn download_into_archive(
+    client: &RetryingClient,
+    mut archive: ArchiveWriter,
+    emoji: &[&Emoji],
+    layout: Layout,
+    settings: &DownloadSettings,
+    global_opts: &GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    handle_interrupts();
+    let pb = progress.start(
+        emoji.len() as u64,
+        "{wide_bar} {pos}/{len:.dim} {prefix} [{eta} left] {msg:<25!}",
+    );
+    let written = download_archive(
+        client,
+        &mut archive,
+        emoji,
+        layout,
+        settings,
+        global_opts,
+        &pb,
+    )
+    .and_then(|summary| archive.finish().map(|_| summary));
+    let summary = match written {
+        Ok(summary) => summary,
+        Err(e) => {
+            pb.abandon_with_message("Could not write the archive");
+            return Err(Error::Io("Could not write the archive".into(), e));
+        }
+    };
+    pb.finish_with_message(&format!("Done! {}, {} retries", summary, client.retries()));
+    if interrupted() {
+        return Err(Error::Interrupted);
+    }
+    if settings.template.is_none() {
+        settings.output_format.emit(&Event::Summary {
+            command: "download",
+            total: summary.downloaded + summary.skipped + summary.failed,
+            ok: summary.downloaded,
+            skipped: summary.skipped,
+            failed: summary.failed,
+            bytes: Some(summary.bytes),
+        });
+    }
+    Ok(Summary::failed(summary.failed))
+}
+
+/// Downloads images into an archive, each next to the JSON of its emoji
+///
+/// Images are requested concurrently but written one after another, so only a few are in memory
+/// at once. The files get the creation time of their emoji.
+fn download_archive(
+    client: &RetryingClient,
+    archive: &mut ArchiveWriter,
+    emoji: &[&Emoji],
+    layout: Layout,
+    settings: &DownloadSettings,
+    global_opts: &GlobalOptions,
+    pb: &dyn Progress,
+) -> std::io::Result<DownloadSummary> {
+    let rate_limiter = RateLimiter::new(settings.rate);
+    let progress = TransferProgress::new(pb, settings.expected_bytes);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut summary = DownloadSummary::default();
+    let concurrency = settings.concurrency.max(1);
+    let (sender, receiver) = std::sync::mpsc::sync_channel(concurrency);
+
+    std::thread::scope(|scope| -> std::io::Result<()> {
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (next, rate_limiter, progress) = (&next, &rate_limiter, &progress);
+            scope.spawn(move || {
+                while let Some(e) = emoji
+                    .get(next.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+                    .filter(|_| !interrupted())
+                {
+                    rate_limiter.wait();
+                    progress.set_message(&e.name);
+                    if global_opts.verbose {
+                        progress.println(&format!("Downloading {}", e.url));
+                    }
+                    let image =
+                        fetch_image_retrying(client, &e.url, None, settings.retries, progress);
+                    // the archive couldn't be written, so there's no point in going on
+                    if sender.send((*e, image)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (e, image) in receiver {
+            let path = layout.dir(std::path::Path::new(""), e).join(e.file_name());
+            let created_unknown = e.unavailable_fields.iter().any(|f| f == "created");
+            let modified = if created_unknown {
+                Timestamp::now()
+            } else {
+                e.created
+            };
+            let mut metadata = e.clone();
+            let outcome = match image {
+                Ok(Some(image)) => {
+                    let extension = downloaded_image_extension(
+                        &image.bytes,
+                        image.content_type.as_deref(),
+                        &e.url,
+                    );
+                    let image_path = path.with_extension(extension);
+                    archive.add(&archive_name(&image_path), &image.bytes, modified)?;
+                    describe_image(&mut metadata, &image.bytes);
+                    // events show where the image is, inside the archive
+                    DownloadOutcome::Downloaded(archive.path.join(image_path))
+                }
+                // without validators there's nothing to be up to date with
+                Ok(None) => DownloadOutcome::Failed,
+                Err(error) => {
+                    pb.println(&format!(
+                        "Could not request {:?}: {}",
+                        e.name,
+                        client.describe_error(error)
+                    ));
+                    DownloadOutcome::Failed
+                }
+            };
+            let json = serde_json::to_string_pretty(&metadata)? + "\n";
+            archive.add(
+                &archive_name(&path.with_extension("json")),
+                json.as_bytes(),
+                modified,
+            )?;
+            settings.report(e, &outcome);
+            // the paths aren't kept, to not grow with the number of emoji
+            match outcome {
+                DownloadOutcome::Downloaded(_) => summary.downloaded += 1,
+                outcome => summary.count(outcome),
+            }
+            pb.inc(1);
+        }
+        Ok(())
+    })?;
+
+    summary.bytes = progress.bytes();
+    summary.retried = progress.retried();
+    Ok(summary)
+}
+
+/// Sets the modification time of a file to when the emoji was added, if that is known
+fn set_created_time(path: &std::path::Path, emoji: &Emoji) -> std::io::Result<()> {
+    if emoji
+        .unavailable_fields
+        .iter()
+        .any(|field| field == "created")
+    {
+        return Ok(());
+    }
+    let created = std::time::UNIX_EPOCH + Duration::from_secs(emoji.created.seconds() as u64);
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_modified(created)
+}
+
+/// Stores what `describe_image` finds out about an image in the emoji's JSON metadata next to it
+///
+/// Does nothing if there is no metadata.
+fn record_image_digest(image: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+    write_image_digest(&metadata_path(image), bytes)
+}
+
+/// Like `record_image_digest`, for metadata that isn't next to its image
+pub(crate) fn write_image_digest(json_path: &std::path::Path, bytes: &[u8]) -> std::io::Result<()> {
+    if !json_path.is_file() {
+        return Ok(());
+    }
+    let mut emoji: Emoji = serde_json::from_slice(&read_decompressed(json_path)?)?;
+    describe_image(&mut emoji, bytes);
+    let serialized = serde_json::to_string_pretty(&emoji)? + "\n";
+    if json_path.extension() == Some("gz".as_ref()) {
+        write_atomically(json_path, &compress_metadata(serialized.as_bytes()))
+    } else {
+        write_atomically(json_path, serialized.as_bytes())
+    }
+}
+
+/// Sets the hash, size, dimensions, and whether it's animated of an emoji's image
+fn describe_image(emoji: &mut Emoji, bytes: &[u8]) {
+    emoji.image_sha256 = Some(sha256_hex(bytes));
+    emoji.image_bytes = Some(bytes.len() as u64);
+    emoji.animated = image_animated(bytes);
+    let (width, height) = image_dimensions(bytes).unzip();
+    emoji.width = width;
+    emoji.height = height;
+}
+
+/// The metadata next to an image, the `.json.gz` if there is one, otherwise the `.json`
+pub(crate) fn metadata_path(image: &std::path::Path) -> PathBuf {
+    let compressed = image.with_extension(COMPRESSED_JSON_EXTENSION);
+    if compressed.is_file() {
+        compressed
+    } else {
+        image.with_extension("json")
+    }
+}
+
+/// Records the hash of an already downloaded image if its metadata doesn't have it yet
+///
+/// Metadata is rewritten by `list`, `backup`, and `sync pull` without the hashes. Metadata
+/// from before images were checked for animation gets that as well, unless the format of the
+/// image is unknown, so it can't be checked.
+fn record_missing_image_digest(image: &std::path::Path, pb: &dyn Progress) {
+    let emoji = read_decompressed(&metadata_path(image))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<Emoji>(&json).ok());
+    let (has_hash, has_animated) = match emoji {
+        Some(emoji) => (emoji.image_sha256.is_some(), emoji.animated.is_some()),
+        None => return,
+    };
+    if has_hash && has_animated {
+        return;
+    }
+    let recorded = read(image).and_then(|bytes| {
+        if has_hash && image_format(&bytes).is_none() {
+            return Ok(());
+        }
+        record_image_digest(image, &bytes)
+    });
+    if let Err(e) = recorded {
+        pb.println(&format!("Could not record hash of {:?}: {}", image, e));
+    }
+}
+
+/// Removes the `.part` files interrupted downloads left in a directory
+fn remove_partial_files(dir: &std::path::Path) -> std::io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(PARTIAL_EXTENSION.as_ref()) {
+            log::debug!("Removing interrupted download {:?}", path);
+            remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod file_name_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    #[test]
+    fn sanitized() {
+        assert_eq!(emoji_file_name("partyparrot"), "partyparrot");
+        assert_eq!(emoji_file_name("blob_wave-2"), "blob_wave-2");
+
+        let imported = emoji_file_name("old:import");
+        assert!(imported.starts_with("old_import-"));
+        assert_eq!(imported.len(), "old_import-".len() + 8);
+        assert_ne!(imported, emoji_file_name("old/import"));
+        assert_ne!(imported, emoji_file_name("old_import"));
+
+        assert!(emoji_file_name("con").starts_with("con-"));
+        assert!(emoji_file_name("LPT1").starts_with("LPT1-"));
+        assert!(emoji_file_name("v1.2").starts_with("v1_2-"));
+        assert!(emoji_file_name("../up").starts_with("___up-"));
+        assert!(emoji_file_name("").starts_with('-'));
+    }
+
+    #[test]
+    fn case_collisions() {
+        let mut emoji: Vec<Emoji> = ["Party", "wave", "party", "PARTY"]
+            .iter()
+            .enumerate()
+            .map(|(created, name)| {
+                let mut e = Emoji::new(name);
+                e.created = Timestamp(created as u128);
+                e
+            })
+            .collect();
+        emoji.reverse();
+        assign_file_names(&mut emoji);
+        emoji.reverse();
+        let file_names: Vec<String> = emoji.iter().map(Emoji::file_name).collect();
+        assert_eq!(file_names, vec!["Party", "wave", "party~1", "PARTY~2"]);
+
+        let dir = TestDir::new("case-collision-test-dir");
+        let mut ford_writer = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false);
+        let pb = indicatif::ProgressBar::hidden();
+        write_emoji_metadata(
+            &mut ford_writer,
+            &emoji,
+            &GlobalOptions::default(),
+            OutputFormat::Text,
+            &pb,
+        );
+        assert!(dir.path.join("party~1.json").is_file());
+
+        // downloads keep using the file names the metadata was written to
+        let mut read: Vec<Emoji> = read_emoji_dir(dir.path).unwrap().collect();
+        read.sort_by(|a, b| a.name.cmp(&b.name));
+        let lower = read.iter().find(|e| e.name == "party").unwrap();
+        assert_eq!(image_path(dir.path, lower), dir.path.join("party~1.png"));
+    }
+
+    #[test]
+    fn failed_metadata() {
+        let dir = TestDir::new("failed-metadata-test-dir");
+        // a directory where the JSON file should go can't be written to
+        std::fs::create_dir_all(dir.path.join("blocked.json")).unwrap();
+        let mut ford_writer = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false);
+        let pb = indicatif::ProgressBar::hidden();
+        let emoji = vec![Emoji::new("fine"), Emoji::new("blocked")];
+        let failed = write_emoji_metadata(
+            &mut ford_writer,
+            &emoji,
+            &GlobalOptions::default(),
+            OutputFormat::Text,
+            &pb,
+        );
+        assert_eq!(failed, 1);
+        assert!(dir.path.join("fine.json").is_file());
+    }
+
+    #[test]
+    fn manifest_file() {
+        let dir = TestDir::new("manifest-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let mut emoji = vec![Emoji::new("wave"), Emoji::new("parrot")];
+        emoji[0]
+            .unknown_fields
+            .insert("is_bad".into(), serde_json::Value::Bool(false));
+        SortKey::Name.sort(&mut emoji, false);
+        write_manifest(dir.path, &emoji).expect("could not write");
+        write_emoji_metadata(
+            &mut FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false),
+            &emoji,
+            &GlobalOptions::default(),
+            OutputFormat::Text,
+            &indicatif::ProgressBar::hidden(),
+        );
+
+        let content = std::fs::read_to_string(dir.path.join(MANIFEST_FILE)).unwrap();
+        let read = parse_emoji_manifest(&content).expect("could not parse");
+        let names: Vec<&str> = read.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["parrot", "wave"]);
+        assert!(read[1].unknown_fields.contains_key("is_bad"));
+        // the per-emoji files don't include the manifest
+        assert_eq!(read_emoji_dir(dir.path).unwrap().count(), 2);
+        assert!(!partial_path(&dir.path.join(MANIFEST_FILE)).exists());
+    }
+
+    #[test]
+    fn stale_manifest() {
+        let dir = TestDir::new("stale-manifest-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        assert!(!manifest_is_current(dir.path));
+
+        let emoji = vec![Emoji::new("wave")];
+        write_emoji_metadata(
+            &mut FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false),
+            &emoji,
+            &GlobalOptions::default(),
+            OutputFormat::Text,
+            &indicatif::ProgressBar::hidden(),
+        );
+        write_manifest(dir.path, &emoji).expect("could not write");
+        let set_modified = |name: &str, seconds: u64| {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.path.join(name))
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+                .unwrap();
+        };
+        set_modified("wave.json", 1_000);
+        set_modified(MANIFEST_FILE, 2_000);
+        assert!(manifest_is_current(dir.path));
+
+        // like after a `list` without `--manifest`, or `download` recording a hash
+        set_modified("wave.json", 3_000);
+        assert!(!manifest_is_current(dir.path));
+    }
+
+    #[test]
+    fn metadata_keeps_name() {
+        let dir = TestDir::new("sanitized-name-test-dir");
+        let mut ford_writer = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false);
+        let emoji = Emoji::new("a:b");
+        ford_writer
+            .write(&emoji.name, serde_json::to_string(&emoji).unwrap())
+            .expect("could not write");
+
+        let names: Vec<String> = read_emoji_dir(dir.path).unwrap().map(|e| e.name).collect();
+        assert_eq!(names, vec!["a:b"]);
+        let path = image_path(dir.path, &emoji);
+        assert_eq!(
+            path.with_extension("json"),
+            dir.path.join(emoji_file_name("a:b")).with_extension("json")
+        );
+        assert!(path.with_extension("json").is_file());
+    }
+
+    #[test]
+    fn compressed_metadata() {
+        let dir = TestDir::new("compressed-metadata-test-dir");
+        let mut ford_writer = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), true);
+        let emoji = vec![Emoji::new("wave"), Emoji::new("a:b")];
+        write_emoji_metadata(
+            &mut ford_writer,
+            &emoji,
+            &GlobalOptions::default(),
+            OutputFormat::Text,
+            &indicatif::ProgressBar::hidden(),
+        );
+        assert!(dir.path.join("wave.json.gz").is_file());
+        assert!(!dir.path.join("wave.json").exists());
+
+        let mut read: Vec<Emoji> = read_emoji_dir(dir.path).unwrap().collect();
+        read.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: Vec<&str> = read.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a:b", "wave"]);
+        assert_eq!(image_path(dir.path, &read[1]), dir.path.join("wave.png"));
+
+        std::fs::write(dir.path.join("wave.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let report = verify_dir(dir.path).expect("could not verify");
+        assert_eq!(report.missing_images, vec!["a:b"]);
+        assert!(report.orphan_images.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod checksum_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    const EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn manifest() {
+        let content = format!("{}  a.png\n{} *b b.gif\n\n", EMPTY, EMPTY.to_uppercase());
+        let checksums = parse_checksums(&content);
+        assert_eq!(checksums.get("a.png").map(String::as_str), Some(EMPTY));
+        assert_eq!(checksums.get("b b.gif").map(String::as_str), Some(EMPTY));
+        assert_eq!(checksums.len(), 2);
+    }
+
+    #[test]
+    fn update_and_verify() {
+        let dir = TestDir::new("checksum-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("a.png"), b"").unwrap();
+        std::fs::write(dir.path.join("b.gif"), b"").unwrap();
+        update_checksums(dir.path, &[]).expect("could not write manifest");
+        assert_eq!(
+            std::fs::read_to_string(dir.path.join(CHECKSUMS_FILE)).unwrap(),
+            format!("{}  a.png\n{}  b.gif\n", EMPTY, EMPTY)
+        );
+
+        std::fs::write(dir.path.join("a.png"), b"changed").unwrap();
+        remove_file(dir.path.join("b.gif")).unwrap();
+        assert_eq!(
+            verify_checksums(dir.path).expect("could not verify"),
+            ChecksumReport {
+                matching: 0,
+                missing: vec!["b.gif".into()],
+                changed: vec!["a.png".into()],
+            }
+        );
+
+        update_checksums(dir.path, &[dir.path.join("a.png")]).expect("could not update");
+        let report = verify_checksums(dir.path).expect("could not verify");
+        assert_eq!(report.matching, 1);
+        assert!(report.missing.is_empty() && report.changed.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod download_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use std::io::Write;
+    use std::time::Instant;
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        assert!(parse_rate("-1").is_err());
+        assert!(parse_rate("inf").is_err());
+
+        let start = Instant::now();
+        let unlimited = RateLimiter::new(0.0);
+        for _ in 0..100 {
+            unlimited.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        let start = Instant::now();
+        let limited = RateLimiter::new(100.0);
+        std::thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| limited.wait());
+            }
+        });
+        // the first request goes through right away
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(url_extension("https://example.com/parrot.GIF?v=1"), "gif");
+        assert_eq!(url_extension("https://example.com/parrot"), "png");
+        assert_eq!(url_extension("alias:parrot"), "png");
+
+        let url = "https://example.com/parrot.png?v=1";
+        assert_eq!(downloaded_image_extension(b"GIF89a", None, url), "gif");
+        assert_eq!(
+            downloaded_image_extension(b"?", Some("image/jpeg; charset=binary"), url),
+            "jpg"
+        );
+        assert_eq!(downloaded_image_extension(b"?", None, url), "png");
+    }
+
+    #[test]
+    fn existing_images() {
+        let dir = TestDir::new("image-path-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let emoji = Emoji::new("parrot");
+        assert_eq!(image_path(dir.path, &emoji), dir.path.join("parrot.png"));
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        assert_eq!(image_path(dir.path, &emoji), dir.path.join("parrot.gif"));
+    }
+
+    #[test]
+    fn aliases_file() {
+        let dir = TestDir::new("aliases-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let mut alias = Emoji::new("party");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+        std::fs::write(
+            dir.path.join("party.json"),
+            serde_json::to_string(&alias).unwrap(),
+        )
+        .unwrap();
+
+        write_aliases_file(dir.path, &[alias]).expect("could not write aliases");
+        assert_eq!(
+            std::fs::read_to_string(dir.path.join(ALIASES_FILE)).unwrap(),
+            "{\n  \"party\": \"parrot\"\n}\n"
+        );
+        let names: Vec<String> = read_emoji_dir(dir.path).unwrap().map(|e| e.name).collect();
+        assert_eq!(names, vec!["party"]);
+    }
+
+    #[test]
+    fn linked_aliases() {
+        let dir = TestDir::new("link-aliases-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        let alias = |name: &str, target: &str| {
+            let mut e = Emoji::new(name);
+            e.is_alias = 1;
+            e.alias_for = target.into();
+            e
+        };
+
+        let dangling = link_aliases(
+            dir.path,
+            Layout::Flat,
+            &[alias("party", "parrot"), alias("broken", "gone")],
+            &[Emoji::new("parrot")],
+        );
+        assert_eq!(dangling, 1);
+        assert_eq!(
+            std::fs::read(dir.path.join("party.gif")).unwrap(),
+            b"GIF89a"
+        );
+        assert!(!dir.path.join("broken.png").exists());
+    }
+
+    #[test]
+    fn layouts() {
+        let base = std::path::Path::new("does-not-exist");
+        let mut emoji = Emoji::new("parrot");
+        emoji.user_display_name = "Jane/Doe".into();
+        emoji.created = Timestamp(1_600_000_000);
+        assert_eq!(
+            Layout::Flat.image_path(base, &emoji),
+            base.join("parrot.png")
+        );
+        assert_eq!(
+            Layout::ByUser.image_path(base, &emoji),
+            base.join("Jane_Doe").join("parrot.png")
+        );
+        assert_eq!(
+            Layout::ByYear.image_path(base, &emoji),
+            base.join("2020").join("parrot.png")
+        );
+
+        assert_eq!(sanitize_directory_name("a\\b: c?"), "a_b_ c_");
+        assert_eq!(sanitize_directory_name(".."), "unknown");
+        assert_eq!(sanitize_directory_name("v1.2"), "v1.2");
+        assert_eq!(sanitize_directory_name(" "), "unknown");
+        assert_eq!(sanitize_directory_name("J. Doe."), "J. Doe");
+    }
+
+    #[test]
+    fn downloads_from_the_cdn() {
+        let dir = TestDir::new("cdn-download-test-dir");
+        std::fs::create_dir_all(dir.path).unwrap();
+        let server = MockServer::start(vec![
+            MockResponse {
+                status: 200,
+                headers: vec![("Content-Type", "image/gif".into())],
+                body: b"GIF89a".to_vec(),
+            },
+            MockResponse::status(404),
+        ]);
+        let mut parrot = Emoji::new("parrot");
+        parrot.url = format!("{}/parrot", server.url);
+        let mut gone = Emoji::new("gone");
+        gone.url = format!("{}/gone.png", server.url);
+        let pairs = vec![
+            (&parrot, image_path(dir.path, &parrot)),
+            (&gone, image_path(dir.path, &gone)),
+        ];
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let settings = DownloadSettings {
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let summary = download_images(&client, &pairs, &settings, &GlobalOptions::default(), &pb);
+
+        assert_eq!((summary.downloaded, summary.failed), (1, 1));
+        // the extension comes from the content type since the URL has none
+        assert_eq!(
+            std::fs::read(dir.path.join("parrot.gif")).unwrap(),
+            b"GIF89a"
+        );
+        assert_eq!(server.finish(), vec!["GET /parrot", "GET /gone.png"]);
+    }
+
+    #[test]
+    fn nested_existing_images() {
+        let dir = TestDir::new("nested-download-test-dir");
+        let user_dir = dir.path.join("M3t0r");
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("parrot.gif"), b"GIF89a").unwrap();
+        let mut emoji = Emoji::new("parrot");
+        emoji.url = "not a url".into();
+        let pairs = vec![(&emoji, Layout::ByUser.image_path(dir.path, &emoji))];
+        assert_eq!(pairs[0].1, user_dir.join("parrot.gif"));
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let global_opts = GlobalOptions::default();
+        let mut settings = DownloadSettings {
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let summary = download_images(&client, &pairs, &settings, &global_opts, &pb);
+        assert_eq!(summary.skipped, 1);
+
+        settings.force = true;
+        let summary = download_images(&client, &pairs, &settings, &global_opts, &pb);
+        assert_eq!(summary.failed, 1);
+        assert!(user_dir.join("parrot.gif").exists());
+    }
+
+    #[test]
+    fn created_times() {
+        let dir = TestDir::new("mtime-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("parrot.gif");
+        std::fs::write(&path, b"GIF89a").unwrap();
+        let modified = || path.metadata().unwrap().modified().unwrap();
+
+        let mut emoji = Emoji::new("parrot");
+        emoji.created = Timestamp(1_600_000_000);
+        set_created_time(&path, &emoji).expect("could not set time");
+        assert_eq!(
+            modified(),
+            std::time::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+        );
+
+        emoji.created = Timestamp(0);
+        emoji.unavailable_fields = vec!["created".into()];
+        set_created_time(&path, &emoji).expect("could not skip");
+        assert_ne!(modified(), std::time::UNIX_EPOCH);
+    }
+
+    #[test]
+    fn stored_objects() {
+        let dir = TestDir::new("cas-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let bytes = b"GIF89a";
+        let hash = sha256_hex(bytes);
+        let object = object_path(dir.path, &hash);
+        assert_eq!(
+            object,
+            dir.path.join("objects").join(&hash[..2]).join(&hash)
+        );
+        std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+        std::fs::write(&object, bytes).unwrap();
+
+        let mut known = Emoji::new("parrot");
+        known.url = "not a url".into();
+        known.image_sha256 = Some(hash.clone());
+        let mut unknown = Emoji::new("wave");
+        unknown.url = "not a url".into();
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let settings = DownloadSettings {
+            rate: 0.0,
+            link_objects: true,
+            ..DownloadSettings::default()
+        };
+        let summary = download_objects(
+            &client,
+            &[&known, &unknown],
+            dir.path,
+            &settings,
+            &GlobalOptions::default(),
+            &pb,
+        );
+        // the known image isn't requested again
+        assert_eq!((summary.skipped, summary.failed), (1, 1));
+        assert_eq!(std::fs::read(dir.path.join("parrot.gif")).unwrap(), bytes);
+
+        let names: std::collections::BTreeMap<String, String> =
+            serde_json::from_slice(&std::fs::read(dir.path.join(NAMES_FILE)).unwrap()).unwrap();
+        assert_eq!(names.len(), 1);
+        assert_eq!(names["parrot"], hash);
+        assert_eq!(read_emoji_dir(dir.path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn unasked_not_modified() {
+        let dir = TestDir::new("cas-not-modified-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let server = MockServer::start(vec![MockResponse::status(304)]);
+        let mut emoji = Emoji::new("parrot");
+        emoji.url = format!("{}/parrot.gif", server.url);
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let settings = DownloadSettings {
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let summary = download_objects(
+            &client,
+            &[&emoji],
+            dir.path,
+            &settings,
+            &GlobalOptions::default(),
+            &pb,
+        );
+        assert_eq!(summary.failed, 1);
+        assert_eq!(server.finish(), vec!["GET /parrot.gif"]);
+    }
+
+    #[test]
+    fn edited_hashes() {
+        let dir = TestDir::new("cas-hash-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("passwd"), b"root").unwrap();
+
+        let hash = sha256_hex(b"GIF89a");
+        assert!(is_sha256_hex(&hash));
+        let mut emoji = Emoji::new("parrot");
+        for edited in [
+            "ü".repeat(32),
+            "../../passwd".into(),
+            hash.to_uppercase(),
+            hash[..63].into(),
+            format!("{}0", hash),
+        ] {
+            assert!(!is_sha256_hex(&edited), "{}", edited);
+            emoji.image_sha256 = Some(edited);
+            assert_eq!(stored_object(dir.path, &emoji), None);
+        }
+    }
+
+    #[test]
+    fn transfer_progress() {
+        let pb = indicatif::ProgressBar::hidden();
+        let progress = TransferProgress::new(&pb, None);
+        let mut body = ProgressBuffer {
+            bytes: vec![],
+            progress: &|bytes| progress.add(bytes),
+        };
+        body.write_all(b"GIF89a").unwrap();
+        body.write_all(&[0; 2048]).unwrap();
+        assert_eq!(body.bytes.len(), 2054);
+        assert_eq!(progress.bytes(), 2054);
+        progress.add(100);
+        progress.discard(100);
+        assert_eq!(progress.bytes(), 2054);
+
+        let summary = DownloadSummary {
+            downloaded: 2,
+            skipped: 3,
+            bytes: progress.bytes(),
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.to_string(),
+            "2 downloaded (2.01KiB), 3 already present, 0 failed"
+        );
+    }
+
+    #[test]
+    fn estimates() {
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let estimate = estimate_download_size(&client, &["not a url", "neither"], 0.0, &pb);
+        assert_eq!(
+            estimate,
+            SizeEstimate {
+                files: 2,
+                bytes: 0,
+                unknown: 2,
+            }
+        );
+
+        let estimate = SizeEstimate {
+            files: 3,
+            bytes: 3 * 1024 * 1024,
+            unknown: 1,
+        };
+        assert_eq!(
+            estimate.to_string(),
+            "3 images to download, 3.00MiB in total, 1 of unknown size"
+        );
+    }
+
+    #[test]
+    fn disk_space() {
+        let dir = TestDir::new("disk-space-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        if cfg!(unix) {
+            assert!(available_space(dir.path).is_some_and(|bytes| bytes > 0));
+        }
+        assert_eq!(available_space(&dir.path.join("missing")), None);
+
+        let full = std::io::Error::from(std::io::ErrorKind::StorageFull);
+        let mut summary = DownloadSummary::default();
+        summary.count(DownloadOutcome::write_failure(&full));
+        summary.count(DownloadOutcome::write_failure(
+            &std::io::ErrorKind::Other.into(),
+        ));
+        assert_eq!(summary.failed, 2);
+        assert!(summary.disk_full);
+    }
+
+    #[test]
+    fn transient_errors() {
+        let client = Client::new();
+        let invalid = client.get("not a url").build().unwrap_err();
+        assert!(!is_transient(&invalid));
+
+        // nothing listens on port 9 of localhost, hopefully
+        let refused = client.get("http://127.0.0.1:9/").send().unwrap_err();
+        assert!(is_transient(&refused));
+
+        let client = RetryingClient::new(client, 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let progress = TransferProgress::new(&pb, None);
+        assert!(fetch_image_retrying(&client, "http://127.0.0.1:9/", None, 1, &progress).is_err());
+        assert_eq!(progress.retried(), 1);
+        assert!(fetch_image_retrying(&client, "not a url", None, 1, &progress).is_err());
+        assert_eq!(progress.retried(), 1);
+
+        // the client already retried server errors, they aren't retried on top of that
+        let server = MockServer::start(vec![
+            MockResponse::status(503).with_header("Retry-After", "0"),
+            MockResponse::status(503),
+        ]);
+        let client = RetryingClient::new(Client::new(), 1);
+        let url = format!("{}/parrot.gif", server.url);
+        match fetch_image_retrying(&client, &url, None, 3, &progress) {
+            Err(error) => assert!(!is_transient(&error)),
+            Ok(_) => panic!("a server error was fetched"),
+        }
+        assert_eq!(server.finish().len(), 2);
+        assert_eq!(progress.retried(), 1);
+    }
+
+    #[test]
+    fn plans() {
+        let dir = TestDir::new("plan-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("present.gif"), b"GIF89a").unwrap();
+        std::fs::write(dir.path.join("empty.png"), b"").unwrap();
+        let emoji: Vec<Emoji> = ["present", "empty", "missing"]
+            .iter()
+            .map(|name| Emoji::new(name))
+            .collect();
+        let selected: Vec<&Emoji> = emoji.iter().collect();
+
+        let plan = plan_download(&selected, dir.path, Layout::Flat, false, false);
+        assert_eq!(plan.present, 1);
+        let pending: Vec<&std::path::Path> =
+            plan.pending.iter().map(|(_, p)| p.as_path()).collect();
+        assert_eq!(
+            pending,
+            vec![dir.path.join("empty.png"), dir.path.join("missing.png")]
+        );
+
+        let plan = plan_download(&selected, dir.path, Layout::Flat, false, true);
+        assert_eq!((plan.present, plan.pending.len()), (0, 3));
+
+        let plan = plan_download(&selected, dir.path, Layout::ByYear, false, false);
+        assert_eq!((plan.present, plan.pending.len()), (0, 3));
+
+        let plan = plan_download(&selected, dir.path, Layout::Flat, true, false);
+        assert_eq!((plan.present, plan.pending.len()), (0, 3));
+        assert_eq!(plan.pending[0].1, dir.path.join(OBJECTS_DIR));
+
+        // planning doesn't write anything
+        let mut files: Vec<_> = read_dir(dir.path)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["empty.png", "present.gif"]);
+    }
+
+    #[test]
+    fn download_state() {
+        let dir = TestDir::new("state-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        assert_eq!(DownloadState::read(dir.path), DownloadState::default());
+        std::fs::write(dir.path.join(STATE_FILE), b"{broken").unwrap();
+        assert_eq!(DownloadState::read(dir.path), DownloadState::default());
+
+        let state = DownloadState {
+            last_created: Timestamp(42),
+        };
+        state.write(dir.path).expect("could not write");
+        assert_eq!(DownloadState::read(dir.path), state);
+        assert_eq!(read_emoji_dir(dir.path).unwrap().count(), 0);
+
+        let emoji: Vec<Emoji> = [10, 20, 30]
+            .iter()
+            .map(|created| {
+                let mut e = Emoji::new(&created.to_string());
+                e.created = Timestamp(*created);
+                e
+            })
+            .collect();
+        let all: Vec<&Emoji> = emoji.iter().collect();
+        assert_eq!(downloaded_until(&all, &[]), Some(Timestamp(30)));
+        assert_eq!(downloaded_until(&all, &[&emoji[1]]), Some(Timestamp(10)));
+        assert_eq!(downloaded_until(&all, &[&emoji[0]]), None);
+        assert_eq!(downloaded_until(&[], &[]), None);
+    }
+
+    #[test]
+    fn orphans() {
+        let dir = TestDir::new("orphan-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        for file in &[
+            "kept.gif",
+            "kept.json",
+            "renamed.PNG",
+            "notes.txt",
+            ALIASES_FILE,
+        ] {
+            std::fs::write(dir.path.join(file), b"").unwrap();
+        }
+        std::fs::create_dir(dir.path.join("sub.png")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("kept.gif", dir.path.join("alias.gif")).unwrap();
+
+        assert_eq!(
+            orphan_images(dir.path).unwrap(),
+            vec![dir.path.join("renamed.PNG")]
+        );
+    }
+
+    #[test]
+    fn atomic_writes() {
+        let dir = TestDir::new("atomic-write-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("parrot.gif");
+        assert_eq!(partial_path(&path), dir.path.join("parrot.gif.part"));
+
+        write_atomically(&path, b"GIF89a").expect("could not write");
+        assert_eq!(std::fs::read(&path).unwrap(), b"GIF89a");
+        assert!(!partial_path(&path).exists());
+
+        std::fs::write(dir.path.join("wave.png.part"), b"trunc").unwrap();
+        remove_partial_files(dir.path).expect("could not clean up");
+        assert!(!dir.path.join("wave.png.part").exists());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn validators() {
+        let dir = TestDir::new("etag-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let image = dir.path.join("parrot.gif");
+        assert_eq!(CacheValidators::read(&image), CacheValidators::default());
+
+        let validators = CacheValidators {
+            etag: Some("\"abc\"".into()),
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".into()),
+        };
+        validators.write(&image).expect("could not write");
+        assert_eq!(
+            std::fs::read_to_string(dir.path.join("parrot.gif.etag")).unwrap(),
+            "ETag: \"abc\"\nLast-Modified: Wed, 21 Oct 2015 07:28:00 GMT\n"
+        );
+        assert_eq!(CacheValidators::read(&image), validators);
+        // an image of the same name with another extension has its own
+        let png = dir.path.join("parrot.png");
+        assert_eq!(CacheValidators::read(&png), CacheValidators::default());
+
+        CacheValidators::default()
+            .write(&image)
+            .expect("could not remove");
+        assert!(!dir.path.join("parrot.gif.etag").exists());
+    }
+
+    #[test]
+    fn digests() {
+        let dir = TestDir::new("digest-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let mut emoji = Emoji::new("parrot");
+        emoji
+            .unknown_fields
+            .insert("is_bad".into(), serde_json::Value::Bool(false));
+        std::fs::write(
+            dir.path.join("parrot.json"),
+            serde_json::to_string(&emoji).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.path.join("parrot.gif"), b"").unwrap();
+
+        record_missing_image_digest(
+            &dir.path.join("parrot.gif"),
+            &indicatif::ProgressBar::hidden(),
+        );
+        let emoji: Emoji =
+            serde_json::from_slice(&read(dir.path.join("parrot.json")).unwrap()).unwrap();
+        assert_eq!(
+            emoji.image_sha256.as_deref(),
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(emoji.image_bytes, Some(0));
+        assert_eq!(
+            emoji.unknown_fields.get("is_bad"),
+            Some(&serde_json::Value::Bool(false))
+        );
+
+        // whether an image of unknown format is animated can't be found out, the hash is enough
+        let read_back = || -> Emoji {
+            serde_json::from_slice(&read(dir.path.join("parrot.json")).unwrap()).unwrap()
+        };
+        std::fs::write(dir.path.join("parrot.gif"), b"no image").unwrap();
+        record_missing_image_digest(
+            &dir.path.join("parrot.gif"),
+            &indicatif::ProgressBar::hidden(),
+        );
+        assert_eq!(read_back().image_bytes, Some(0));
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        record_missing_image_digest(
+            &dir.path.join("parrot.gif"),
+            &indicatif::ProgressBar::hidden(),
+        );
+        assert_eq!(read_back().image_bytes, Some(6));
+
+        // images without metadata are left alone
+        record_image_digest(&dir.path.join("orphan.png"), b"png").expect("could not record");
+        assert!(!dir.path.join("orphan.json").exists());
+    }
+
+    #[test]
+    fn summary() {
+        let dir = TestDir::new("download-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("exists.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        std::fs::write(dir.path.join("empty.png"), b"").unwrap();
+        let emoji: Vec<Emoji> = ["exists", "empty", "invalid"]
+            .iter()
+            .map(|name| {
+                let mut e = Emoji::new(name);
+                e.url = "not a url".into();
+                e
+            })
+            .collect();
+        let pairs: Vec<(&Emoji, PathBuf)> = emoji
+            .iter()
+            .map(|e| (e, dir.path.join(&e.name).with_extension("png")))
+            .collect();
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let global_opts = GlobalOptions::default();
+        let settings = DownloadSettings {
+            concurrency: 3,
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let summary = download_images(&client, &pairs, &settings, &global_opts, &pb);
+        assert_eq!(
+            summary,
+            DownloadSummary {
+                downloaded: 0,
+                skipped: 1,
+                failed: 2,
+                written: vec![],
+                bytes: 0,
+                retried: 0,
+                disk_full: false,
+            }
+        );
+        assert_eq!(pb.position(), 3);
+    }
+
+    #[test]
+    fn selection() {
+        let emoji = vec![
+            Emoji::new("partyparrot"),
+            Emoji::new("blob-wave"),
+            Emoji::new("blob-dance"),
+        ];
+        let mut alias = Emoji::new("parrot");
+        alias.is_alias = 1;
+        alias.alias_for = "partyparrot".into();
+        let aliases = vec![alias];
+        let names = |selected: Vec<&Emoji>| {
+            selected
+                .iter()
+                .map(|e| e.name.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let (selected, unknown) = select_emoji(&emoji, &aliases, &[], None);
+        assert_eq!(selected.len(), 3);
+        assert!(unknown.is_empty());
+
+        let requested = vec!["blob-wave".to_string(), "parrot".into(), "nope".into()];
+        let (selected, unknown) = select_emoji(&emoji, &aliases, &requested, None);
+        assert_eq!(names(selected), vec!["partyparrot", "blob-wave"]);
+        assert_eq!(unknown, vec!["nope"]);
+
+        let filter = regex::Regex::new("^blob-").unwrap();
+        let (selected, _) = select_emoji(&emoji, &aliases, &[], Some(&filter));
+        assert_eq!(names(selected), vec!["blob-wave", "blob-dance"]);
+        let (selected, _) = select_emoji(&emoji, &aliases, &requested, Some(&filter));
+        assert_eq!(names(selected), vec!["blob-wave"]);
+    }
+
+    #[test]
+    fn manifests() {
+        let one = serde_json::to_string_pretty(&Emoji::new("parrot")).unwrap();
+        let two = serde_json::to_string(&Emoji::new("wave")).unwrap();
+        let names = |content: &str| {
+            parse_emoji_manifest(content)
+                .expect("could not parse")
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(
+            names(&format!("{}\n{}\n", one, two)),
+            vec!["parrot", "wave"]
+        );
+        assert_eq!(names(&format!("{}{}", two, one)), vec!["wave", "parrot"]);
+        assert_eq!(
+            names(&format!(" [{}, {}]", one, two)),
+            vec!["parrot", "wave"]
+        );
+        assert!(names("").is_empty());
+        assert!(parse_emoji_manifest(&format!("{}, {}", one, two)).is_err());
+    }
+
+    #[test]
+    fn name_lists() {
+        let list = "partyparrot\n\n# comment\n  blob-wave  \n./emoji/cat.json\nv1.2\n";
+        assert_eq!(
+            parse_name_list(list),
+            vec!["partyparrot", "blob-wave", "cat", "v1.2"]
+        );
+    }
+
+    #[test]
+    fn forced_selection() {
+        let dir = TestDir::new("selection-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let mut emoji = vec![Emoji::new("wanted"), Emoji::new("other")];
+        for e in &mut emoji {
+            e.url = "not a url".into();
+            std::fs::write(
+                dir.path.join(&e.name).with_extension("png"),
+                b"\x89PNG\r\n\x1a\n",
+            )
+            .unwrap();
+        }
+        let (selected, _) = select_emoji(&emoji, &[], &["wanted".to_string()], None);
+        let pairs: Vec<(&Emoji, PathBuf)> = selected
+            .iter()
+            .map(|e| (*e, image_path(dir.path, e)))
+            .collect();
+
+        let client = RetryingClient::new(Client::new(), 0);
+        let pb = indicatif::ProgressBar::hidden();
+        let global_opts = GlobalOptions::default();
+        let settings = DownloadSettings {
+            force: true,
+            rate: 0.0,
+            ..DownloadSettings::default()
+        };
+        let summary = download_images(&client, &pairs, &settings, &global_opts, &pb);
+        // only the selected image is downloaded again, despite force
+        assert_eq!(summary.failed, 1);
+        assert_eq!(pb.position(), 1);
+        assert!(dir.path.join("other.png").exists());
+    }
+}
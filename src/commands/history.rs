@@ -0,0 +1,216 @@
+//! `history`, of the emoji in snapshots
+
+use crate::*;
+
+/// Parses either a JSON array of emoji or a sequence of concatenated emoji JSON documents
+///
+/// The former is written by `snapshot`, the latter by `list` when writing to a file.
+fn parse_emoji_documents(content: &str) -> serde_json::Result<Vec<Emoji>> {
+    let mut emoji = vec![];
+    for value in serde_json::Deserializer::from_str(content).into_iter::<serde_json::Value>() {
+        match value? {
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    emoji.push(serde_json::from_value(value)?);
+                }
+            }
+            value => emoji.push(serde_json::from_value(value)?),
+        }
+    }
+    Ok(emoji)
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct HistoryEntry {
+    name: String,
+    user_display_name: String,
+    created: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias_for: Option<String>,
+}
+
+impl From<&Emoji> for HistoryEntry {
+    fn from(e: &Emoji) -> HistoryEntry {
+        HistoryEntry {
+            name: e.name.clone(),
+            user_display_name: e.user_display_name.clone(),
+            created: e.created,
+            alias_for: if e.is_alias != 0 {
+                Some(e.alias_for.clone())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// An emoji that points somewhere else now, `None` meaning it's a real emoji and not an alias
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct Repointed {
+    name: String,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct History {
+    added: Vec<HistoryEntry>,
+    removed: Vec<HistoryEntry>,
+    repointed: Vec<Repointed>,
+}
+
+impl History {
+    pub fn new(old: &[Emoji], new: &[Emoji]) -> History {
+        let (removed, added, in_both) = diff_names(old, new);
+        let find = |emoji: &[Emoji], name: &str| -> HistoryEntry {
+            emoji
+                .iter()
+                .find(|e| e.name == name)
+                .expect("diff_names only returns names that exist")
+                .into()
+        };
+        let alias_for = |e: &Emoji| {
+            if e.is_alias != 0 {
+                Some(e.alias_for.clone())
+            } else {
+                None
+            }
+        };
+
+        History {
+            added: added.iter().map(|name| find(new, name)).collect(),
+            removed: removed.iter().map(|name| find(old, name)).collect(),
+            repointed: in_both
+                .into_iter()
+                .filter(|(o, n)| alias_for(o) != alias_for(n))
+                .map(|(o, n)| Repointed {
+                    name: o.name.clone(),
+                    from: alias_for(o),
+                    to: alias_for(n),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let describe = |alias_for: &Option<String>| match alias_for {
+            Some(target) => format!("alias for {}", target),
+            None => "emoji".to_string(),
+        };
+
+        writeln!(f, "Added ({}):", self.added.len())?;
+        for e in &self.added {
+            writeln!(
+                f,
+                "  + {} ({}) by {} on {}",
+                e.name,
+                describe(&e.alias_for),
+                e.user_display_name,
+                e.created
+            )?;
+        }
+        writeln!(f, "Removed ({}):", self.removed.len())?;
+        for e in &self.removed {
+            writeln!(f, "  - {} ({})", e.name, describe(&e.alias_for))?;
+        }
+        writeln!(f, "Re-pointed ({}):", self.repointed.len())?;
+        for r in &self.repointed {
+            writeln!(
+                f,
+                "  ~ {}: {} -> {}",
+                r.name,
+                describe(&r.from),
+                describe(&r.to)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn history(history_opts: HistoryOptions) -> Result<Summary, Error> {
+    if history_opts.old.as_os_str() == "-" && history_opts.new.as_os_str() == "-" {
+        return Err(Error::InvalidArguments(
+            "Only one of the snapshots can be read from STDIN".into(),
+        ));
+    }
+
+    let load = |path: &std::path::Path| {
+        let content =
+            read_to_string(path).map_err(|e| Error::Io(format!("Could not read {:?}", path), e))?;
+        parse_emoji_documents(&content)
+            .map_err(|e| Error::Serialization(format!("Could not read {:?}", path), e))
+    };
+    let old = load(&history_opts.old)?;
+    let new = load(&history_opts.new)?;
+
+    let history = History::new(&old, &new);
+    match history_opts.output {
+        OutputFormat::Json => print_json(&history)?,
+        OutputFormat::Text => print!("{}", history),
+    }
+    Ok(Summary::done())
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    #[test]
+    fn documents() {
+        let array = serde_json::to_string(&vec![Emoji::new("a"), Emoji::new("b")]).unwrap();
+        let stream = format!(
+            "{}\n{}\n",
+            serde_json::to_string_pretty(&Emoji::new("a")).unwrap(),
+            serde_json::to_string_pretty(&Emoji::new("b")).unwrap()
+        );
+        for content in &[array, stream] {
+            let emoji = parse_emoji_documents(content).expect("could not parse");
+            assert_eq!(emoji.len(), 2);
+            assert_eq!(emoji[1].name, "b");
+        }
+        assert!(parse_emoji_documents("{\"name\": 1}").is_err());
+    }
+
+    #[test]
+    fn changes() {
+        let alias = |name: &str, target: &str| {
+            let mut e = Emoji::new(name);
+            e.is_alias = 1;
+            e.alias_for = target.into();
+            e
+        };
+        let old = vec![
+            Emoji::new("kept"),
+            Emoji::new("removed"),
+            alias("moved", "kept"),
+            Emoji::new("became-alias"),
+        ];
+        let new = vec![
+            Emoji::new("kept"),
+            alias("moved", "added"),
+            alias("became-alias", "kept"),
+            Emoji::new("added"),
+        ];
+
+        let history = History::new(&old, &new);
+        assert_eq!(history.added, vec![HistoryEntry::from(&new[3])]);
+        assert_eq!(history.removed, vec![HistoryEntry::from(&old[1])]);
+        assert_eq!(
+            history.repointed,
+            vec![
+                Repointed {
+                    name: "became-alias".into(),
+                    from: None,
+                    to: Some("kept".into()),
+                },
+                Repointed {
+                    name: "moved".into(),
+                    from: Some("kept".into()),
+                    to: Some("added".into()),
+                },
+            ]
+        );
+    }
+}
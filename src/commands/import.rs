@@ -0,0 +1,503 @@
+//! `import`, from emojipacks YAML files
+
+use crate::*;
+use std::fs::read;
+
+/// An emoji of an emojipacks YAML file, with the line its entry starts on
+#[derive(Debug, PartialEq)]
+struct PackEntry {
+    line: usize,
+    name: String,
+    source: PackSource,
+}
+
+#[derive(Debug, PartialEq)]
+enum PackSource {
+    /// An URL or a path relative to the YAML file
+    Src(String),
+    AliasOf(String),
+}
+
+/// Reads the `emojis` list of an emojipacks YAML file
+///
+/// Only the block style emojipacks uses is understood. Entries that can't be read are errors
+/// mentioning their line, without affecting the other entries.
+fn parse_emojipacks(yaml: &str) -> Vec<Result<PackEntry, String>> {
+    /// The key and value of each line of an entry
+    type Fields = Vec<(String, Result<String, String>)>;
+
+    fn value(raw: &str) -> Result<String, String> {
+        let raw = raw.trim();
+        if raw.starts_with('"') {
+            serde_json::from_str(raw).map_err(|_| format!("invalid quoted string {}", raw))
+        } else if let Some(quoted) = raw.strip_prefix('\'') {
+            quoted
+                .strip_suffix('\'')
+                .map(|quoted| quoted.replace("''", "'"))
+                .ok_or_else(|| format!("invalid quoted string {}", raw))
+        } else {
+            let raw = raw.split(" #").next().unwrap_or_default();
+            Ok(raw.trim_end().to_string())
+        }
+    }
+
+    fn entry(line: usize, fields: Fields) -> Result<PackEntry, String> {
+        let mut name = None;
+        let mut source = None;
+        for (key, value) in fields {
+            let value = value.map_err(|e| format!("line {}: {}: {}", line, key, e))?;
+            match key.as_str() {
+                "name" => name = Some(value),
+                "src" if source.is_none() => source = Some(PackSource::Src(value)),
+                "alias_of" if source.is_none() => source = Some(PackSource::AliasOf(value)),
+                "src" | "alias_of" => {
+                    return Err(format!("line {}: has both src and alias_of", line))
+                }
+                _ => {}
+            }
+        }
+        match (name.filter(|n| !n.is_empty()), source) {
+            (Some(name), Some(source)) => Ok(PackEntry { line, name, source }),
+            (None, _) => Err(format!("line {}: has no name", line)),
+            (Some(name), None) => Err(format!("line {}: {} has no src or alias_of", line, name)),
+        }
+    }
+
+    let mut entries = vec![];
+    let mut in_emojis = false;
+    // the line the entry being read started on, and its fields so far
+    let mut current: Option<(usize, Fields)> = None;
+    for (number, line) in yaml.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---" {
+            continue;
+        }
+        if !line.starts_with(' ') && !line.starts_with('-') {
+            entries.extend(current.take().map(|(line, fields)| entry(line, fields)));
+            in_emojis = trimmed == "emojis:";
+            continue;
+        }
+        if !in_emojis {
+            continue;
+        }
+
+        let field = match trimmed.strip_prefix('-') {
+            Some(rest) => {
+                entries.extend(current.take().map(|(line, fields)| entry(line, fields)));
+                current = Some((number, vec![]));
+                rest.trim()
+            }
+            None => trimmed,
+        };
+        if field.is_empty() {
+            continue;
+        }
+        let fields = match &mut current {
+            Some((_, fields)) => fields,
+            None => {
+                entries.push(Err(format!(
+                    "line {}: expected an entry starting with -",
+                    number
+                )));
+                continue;
+            }
+        };
+        match field.split_once(':') {
+            Some((key, raw)) if !key.contains(['"', '\'', '{', '[']) => {
+                fields.push((key.trim().to_string(), value(raw)));
+            }
+            _ => fields.push((
+                "entry".into(),
+                Err(format!("expected a \"key: value\" line, got {:?}", field)),
+            )),
+        }
+    }
+    entries.extend(current.take().map(|(line, fields)| entry(line, fields)));
+    entries
+}
+
+pub(crate) fn import(
+    client: &RetryingClient,
+    import_opts: ImportOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = import_opts.global + global_opts;
+    let slack = import_opts.slack;
+    require_resize(import_opts.resize.auto_resize)?;
+    let path = &import_opts.path;
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("Could not read {:?}", path), e))?;
+    let base_path = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+
+    let mut failed = 0;
+    let mut entries = vec![];
+    for entry in parse_emojipacks(&yaml) {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                log::error!("{:?}, {}", path, e);
+                failed += 1;
+            }
+        }
+    }
+    // aliases can only be created once the emoji they point to exist
+    entries.sort_by_key(|entry| matches!(entry.source, PackSource::AliasOf(_)));
+
+    // the whole emoji are needed to put them back if replacing them fails
+    let existing: std::collections::HashMap<String, Emoji> =
+        get_emoji(client, &slack.workspace, &slack.token)
+            .map_err(|e| Error::Api("Could not get emojis".into(), e))?
+            .into_iter()
+            .map(|e| (normalize_emoji_name(&e.name), e))
+            .collect();
+    let pb = progress.start(entries.len() as u64, PROGRESS_TEMPLATE);
+    let rate_limiter = RateLimiter::new(20.0); // 20 requests / s
+    let (mut added, mut skipped) = (0, 0);
+
+    for entry in track(&pb, entries.iter().take_while(|_| !interrupted())) {
+        let name = normalize_emoji_name(&entry.name);
+        pb.set_message(&name);
+
+        let old = existing.get(&name);
+        if old.is_some() && !import_opts.replace {
+            if global_opts.verbose {
+                pb.println(&format!("{}: already exists, skipping", name));
+            }
+            skipped += 1;
+            continue;
+        }
+
+        // the image is ready before an existing emoji is removed, a broken one doesn't cost it
+        let (workspace, token, name) = (&slack.workspace, &slack.token, &name);
+        let add: Box<dyn FnOnce() -> Result<(), slack_emoji::Error>> = match &entry.source {
+            PackSource::AliasOf(target) => {
+                if global_opts.verbose {
+                    pb.println(&format!("Adding alias {} for {}", name, target));
+                }
+                Box::new(move || add_alias(client, workspace, token, name, target))
+            }
+            PackSource::Src(src) => {
+                let image = match pack_image(client, base_path, src) {
+                    Ok(image) => image,
+                    Err(error) => {
+                        pb.println(&format!("{} (line {}): {}", name, entry.line, error));
+                        failed += 1;
+                        continue;
+                    }
+                };
+                let image =
+                    match fit_upload_limits(name, image, import_opts.resize.auto_resize, &*pb) {
+                        Ok(image) => image,
+                        Err(error) => {
+                            pb.println(&format!("{} (line {}): {}", name, entry.line, error));
+                            failed += 1;
+                            continue;
+                        }
+                    };
+                if global_opts.verbose {
+                    pb.println(&format!("Uploading {} from {}", name, src));
+                }
+                Box::new(move || add_emoji(client, workspace, token, name, image))
+            }
+        };
+        let result = match old {
+            Some(old) => {
+                if global_opts.verbose {
+                    pb.println(&format!("Replacing existing {}", name));
+                }
+                replace_emoji(client, &slack, old, add)
+            }
+            None => add().map_err(|e| e.to_string()),
+        };
+        match result {
+            Ok(()) => added += 1,
+            Err(error) => {
+                pb.println(&format!("{}: Could not import: {}", name, error));
+                failed += 1;
+            }
+        }
+
+        rate_limiter.wait();
+    }
+
+    if interrupted() {
+        pb.abandon_with_message(&format!(
+            "Interrupted after {} of {} emoji",
+            pb.position(),
+            entries.len()
+        ));
+        return Err(Error::Interrupted);
+    }
+    pb.finish_with_message(&format!(
+        "Done! {} imported, {} skipped, {} failed",
+        added, skipped, failed
+    ));
+    Ok(Summary::failed(failed))
+}
+
+/// The image of an emojipacks entry, downloaded from any host or read relative to the pack
+///
+/// Since packs come from anywhere, the result has to be an image. Whether it fits Slack's limits
+/// is up to `fit_upload_limits`, which can scale it down with --auto-resize.
+fn pack_image(
+    client: &RetryingClient,
+    base_path: &std::path::Path,
+    src: &str,
+) -> Result<Vec<u8>, String> {
+    let image = match reqwest::Url::parse(src) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => {
+            download_image(client, url.as_str())
+                .map_err(|e| format!("Could not download {}: {}", src, e))?
+        }
+        Ok(url) => return Err(format!("Unsupported URL {}", url)),
+        Err(_) => {
+            let path = base_path.join(src);
+            read(&path).map_err(|e| format!("Could not read {:?}: {}", path, e))?
+        }
+    };
+    if image_format(&image).is_none() {
+        return Err(format!("{} is not a PNG, GIF, JPEG, or WebP image", src));
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use structopt::StructOpt;
+
+    fn entry(line: usize, name: &str, source: PackSource) -> Result<PackEntry, String> {
+        Ok(PackEntry {
+            line,
+            name: name.into(),
+            source,
+        })
+    }
+
+    #[test]
+    fn parses_emojipacks() {
+        let yaml = "title: parrots\n\
+                    emojis:\n\
+                    # the classic\n\
+                    - name: parrot\n  \
+                      src: https://example.com/parrot.gif # animated\n\
+                    - name: 'it''s'\n  \
+                      alias_of: \"parrot\"\n\
+                    - src: https://example.com/nameless.gif\n\
+                    - name: both\n  \
+                      src: a.png\n  \
+                      alias_of: parrot\n\
+                    - name: \"unterminated\n  \
+                      src: b.png\n\
+                    - name: fine\n  \
+                      src: images/fine.png\n";
+        assert_eq!(
+            parse_emojipacks(yaml),
+            vec![
+                entry(
+                    4,
+                    "parrot",
+                    PackSource::Src("https://example.com/parrot.gif".into())
+                ),
+                entry(6, "it's", PackSource::AliasOf("parrot".into())),
+                Err("line 8: has no name".into()),
+                Err("line 9: has both src and alias_of".into()),
+                Err("line 12: name: invalid quoted string \"unterminated".into()),
+                entry(14, "fine", PackSource::Src("images/fine.png".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_what_list_writes() {
+        let mut parrot = Emoji::new("parrot");
+        parrot.url = "https://emoji.slack-edge.com/T1/parrot/abc.gif".into();
+        let mut alias = Emoji::new("party \"parrot\"");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+
+        let yaml = emojipacks_yaml("acme", &[parrot, alias], None, std::path::Path::new(""));
+        assert_eq!(
+            parse_emojipacks(&yaml),
+            vec![
+                entry(
+                    3,
+                    "parrot",
+                    PackSource::Src("https://emoji.slack-edge.com/T1/parrot/abc.gif".into())
+                ),
+                entry(5, "party \"parrot\"", PackSource::AliasOf("parrot".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_local_images_list_refers_to() {
+        let dir = TestDir::new("local-src-test-dir");
+        let images = dir.path.join("images");
+        let packs = dir.path.join("packs");
+        std::fs::create_dir_all(&images).unwrap();
+        std::fs::create_dir(&packs).unwrap();
+        std::fs::write(images.join("parrot.gif"), b"GIF89a").unwrap();
+
+        let yaml = emojipacks_yaml("acme", &[Emoji::new("parrot")], Some(&images), &packs);
+        let src = "../images/parrot.gif";
+        assert_eq!(
+            parse_emojipacks(&yaml),
+            vec![entry(3, "parrot", PackSource::Src(src.into()))]
+        );
+        let client = RetryingClient::new(Client::new(), 0);
+        assert_eq!(pack_image(&client, &packs, src).unwrap(), b"GIF89a");
+        // images too large for Slack are left to --auto-resize
+        let mut big = b"GIF89a".to_vec();
+        big.resize(MAX_EMOJI_BYTES as usize * 2, 0);
+        std::fs::write(images.join("parrot.gif"), &big).unwrap();
+        assert_eq!(pack_image(&client, &packs, src).unwrap(), big);
+        // relative to the current directory when the YAML goes to STDOUT
+        let yaml = emojipacks_yaml(
+            "acme",
+            &[Emoji::new("parrot")],
+            Some(&images),
+            std::path::Path::new(""),
+        );
+        assert!(yaml.contains("src: \"local-src-test-dir/images/parrot.gif\""));
+    }
+
+    #[test]
+    fn imports_a_pack() {
+        let dir = TestDir::new("import-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("not-an-image.png"), b"<html>").unwrap();
+        let server = MockServer::start(vec![
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "custom_emoji_total_count": 1,
+                "paging": {"count": 1000, "page": 1, "pages": 1},
+                "emoji": [{"name": "parrot", "url": "https://x/parrot.gif", "created": 1}],
+            })),
+            MockResponse {
+                status: 200,
+                headers: vec![("Content-Type", "image/gif".into())],
+                body: b"GIF89a".to_vec(),
+            },
+            MockResponse::json(serde_json::json!({"ok": true})),
+            MockResponse::json(serde_json::json!({"ok": true})),
+        ]);
+        let pack = dir.path.join("pack.yaml");
+        std::fs::write(
+            &pack,
+            format!(
+                "emojis:\n\
+                 - name: party\n  alias_of: blob\n\
+                 - name: parrot\n  src: {url}/parrot.gif\n\
+                 - name: blob\n  src: {url}/blob.gif\n\
+                 - name: broken\n  src: not-an-image.png\n\
+                 - name: nothing\n",
+                url = server.url
+            ),
+        )
+        .unwrap();
+
+        let args = [
+            "slack-emoji",
+            "import",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+        ];
+        let import_opts = match Cli::from_iter_safe(args.iter().chain(&[pack.to_str().unwrap()]))
+            .unwrap()
+            .command
+        {
+            Commands::Import(import_opts) => import_opts,
+            command => panic!("not an import command: {:?}", command),
+        };
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        let summary = import(
+            &client,
+            import_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+        .unwrap();
+
+        // the entry without a source and the one that isn't an image failed
+        assert_eq!(summary.failed, 2);
+        assert_eq!(
+            server.finish(),
+            vec![
+                "POST /api/emoji.adminList",
+                "GET /blob.gif",
+                "POST /api/emoji.add",
+                "POST /api/emoji.add"
+            ]
+        );
+    }
+
+    #[test]
+    fn puts_back_what_it_could_not_replace() {
+        let dir = TestDir::new("import-replace-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let gif = || MockResponse {
+            status: 200,
+            headers: vec![("Content-Type", "image/gif".into())],
+            body: b"GIF89a".to_vec(),
+        };
+        let images = MockServer::start(vec![gif(), gif()]);
+        let server = MockServer::start(vec![
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "custom_emoji_total_count": 1,
+                "paging": {"count": 1000, "page": 1, "pages": 1},
+                "emoji": [
+                    {"name": "parrot", "url": format!("{}/old.gif", images.url), "created": 1}
+                ],
+            })),
+            MockResponse::json(serde_json::json!({"ok": true})),
+            MockResponse::json(serde_json::json!({"ok": false, "error": "error_bad_upload"})),
+            MockResponse::json(serde_json::json!({"ok": true})),
+        ]);
+        let pack = dir.path.join("pack.yaml");
+        let yaml = format!("emojis:\n- name: parrot\n  src: {}/new.gif\n", images.url);
+        std::fs::write(&pack, yaml).unwrap();
+
+        let args = [
+            "slack-emoji",
+            "import",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+            "--replace",
+            pack.to_str().unwrap(),
+        ];
+        let import_opts = match Cli::from_iter_safe(args).unwrap().command {
+            Commands::Import(import_opts) => import_opts,
+            command => panic!("not an import command: {:?}", command),
+        };
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        let summary = import(
+            &client,
+            import_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+        .unwrap();
+
+        assert_eq!(summary.failed, 1);
+        // the new image is fetched before, the old one to put it back
+        assert_eq!(images.finish(), vec!["GET /new.gif", "GET /old.gif"]);
+        assert_eq!(
+            server.finish(),
+            vec![
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.remove",
+                "POST /api/emoji.add",
+                "POST /api/emoji.add"
+            ]
+        );
+    }
+}
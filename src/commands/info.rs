@@ -0,0 +1,81 @@
+//! `info`
+
+use crate::*;
+
+pub(crate) fn info(
+    client: &RetryingClient,
+    info_opts: InfoOptions,
+    global_opts: GlobalOptions,
+) -> Result<Summary, Error> {
+    let global_opts = info_opts.global + global_opts;
+    let slack = info_opts.slack;
+    let name = &info_opts.name;
+
+    let emoji = get_emoji(client, &slack.workspace, &slack.token)
+        .map_err(|e| Error::Api("Could not get emojis".into(), e))?;
+
+    let e = match emoji.iter().find(|e| &e.name == name) {
+        Some(e) => e,
+        None => {
+            let mut message = format!("No emoji named {} in {}", name, slack.workspace);
+            let similar = similar_names(name, emoji.iter().map(|e| e.name.as_str()));
+            if !similar.is_empty() {
+                message += &format!("\nDid you mean: {}", similar.join(", "));
+            }
+            return Err(Error::Failed(message));
+        }
+    };
+
+    println!("Name:      {}", e.name);
+    if e.is_alias != 0 {
+        println!("Alias for: {}", e.alias_for);
+    } else {
+        println!("URL:       {}", e.url);
+    }
+    match &e.user_id {
+        Some(user_id) => println!("Creator:   {} ({})", e.user_display_name, user_id),
+        None => println!("Creator:   {}", e.user_display_name),
+    }
+    println!("Created:   {}", e.created);
+
+    let aliases: Vec<&str> = emoji
+        .iter()
+        .filter(|a| a.is_alias != 0 && &a.alias_for == name)
+        .map(|a| a.name.as_str())
+        .collect();
+    if !aliases.is_empty() {
+        println!("Aliases:   {}", aliases.join(", "));
+    }
+
+    if global_opts.verbose {
+        for (field, value) in &e.unknown_fields {
+            println!("{}: {}", field, value);
+        }
+    }
+    Ok(Summary::done())
+}
+
+#[cfg(test)]
+mod info_tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(Timestamp(0).to_string(), "1970-01-01 00:00:00 UTC");
+        assert_eq!(Timestamp(1717243199).to_string(), "2024-06-01 11:59:59 UTC");
+    }
+
+    #[test]
+    fn snapshot_names() {
+        assert_eq!(snapshot_file_name(1717243200), "2024-06-01T12-00-00.json");
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = vec!["partyparrot", "party_parrot", "parrot", "blob-wave"];
+        assert_eq!(
+            similar_names("party-parrot", names.into_iter()),
+            vec!["party_parrot", "partyparrot"]
+        );
+    }
+}
@@ -0,0 +1,1284 @@
+//! `list`, and the formats it writes
+
+use crate::*;
+use std::convert::TryInto;
+use std::fs::remove_file;
+use std::path::PathBuf;
+
+pub(crate) fn list(
+    client: &RetryingClient,
+    list_opts: ListOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = list_opts.global + global_opts;
+
+    if list_opts.output_format == OutputFormat::Json && (list_opts.count_only || list_opts.table) {
+        return Err(Error::InvalidArguments(
+            "--count-only and --table print for humans, not as --output-format json".into(),
+        ));
+    }
+    if list_opts.count_only {
+        let count = get_emoji_count(client, &list_opts.slack.workspace, &list_opts.slack.token)
+            .map_err(|e| Error::Api("Could not count emojis".into(), e))?;
+        println!("{}", count);
+        return Ok(Summary::done());
+    }
+
+    let single_file = list_opts.format.filter(|f| *f != ListFormat::Json);
+    if let (Some(format), true) = (
+        single_file,
+        list_opts.names_only || list_opts.table || list_opts.manifest,
+    ) {
+        return Err(Error::InvalidArguments(format!(
+            "--format {} can't be combined with --names-only, --table, or --manifest",
+            format.name()
+        )));
+    }
+    if list_opts.local_src.is_some() && list_opts.format != Some(ListFormat::Emojipacks) {
+        return Err(Error::InvalidArguments(
+            "--local-src only works with --format emojipacks".into(),
+        ));
+    }
+    if list_opts
+        .format_string
+        .as_ref()
+        .is_some_and(Template::shows_download)
+    {
+        return Err(Error::InvalidArguments(
+            "{status} and {path} are only known when downloading".into(),
+        ));
+    }
+    if list_opts.group_by.is_some() && list_opts.format != Some(ListFormat::Markdown) {
+        return Err(Error::InvalidArguments(
+            "--group-by only works with --format markdown".into(),
+        ));
+    }
+    // the database is updated in place, so it isn't opened and truncated like other outputs
+    let database = match (list_opts.format, &list_opts.output) {
+        (Some(ListFormat::Sqlite), _) if !cfg!(feature = "sqlite") => {
+            return Err(Error::InvalidArguments(NO_SQLITE.into()))
+        }
+        (Some(ListFormat::Sqlite), Some(path)) if path.as_os_str() != "-" && !path.is_dir() => {
+            Some(path.clone())
+        }
+        (Some(ListFormat::Sqlite), _) => {
+            return Err(Error::InvalidArguments(
+                "--format sqlite needs the database file as --output".into(),
+            ))
+        }
+        _ => None,
+    };
+    // the table, names, and single files go to STDOUT instead of the usual directory
+    let to_stdout = list_opts.names_only
+        || list_opts.table
+        || single_file.is_some()
+        || list_opts.format_string.is_some();
+    let mut ford_writer: FileOrDirectoryWriter = match list_opts
+        .output
+        .clone()
+        .filter(|_| database.is_none())
+        .or_else(|| Some(PathBuf::from("-")).filter(|_| to_stdout))
+        .or_else(|| list_opts.slack.profile_output.clone())
+        .unwrap_or(PathBuf::from(
+            workspace_name(&list_opts.slack.workspace) + "/",
+        ))
+        .try_into()
+    {
+        Ok(ford_writer) => ford_writer,
+        Err(e) => return Err(Error::Io("Could not open the output".into(), e)),
+    };
+
+    let is_directory = matches!(ford_writer, FileOrDirectoryWriter::Directory(..));
+    let one_file = single_file.filter(|f| *f != ListFormat::Markdown);
+    if let (Some(format), true) = (one_file, is_directory) {
+        return Err(Error::InvalidArguments(format!(
+            "--format {} writes all emoji into one file, so --output can't be a directory",
+            format.name()
+        )));
+    }
+    let format = match list_opts.format {
+        Some(format) => format,
+        None if is_directory => ListFormat::Json,
+        None => ListFormat::Ndjson,
+    };
+    if list_opts.compress {
+        if format != ListFormat::Json || list_opts.format_string.is_some() {
+            return Err(Error::InvalidArguments(
+                "--compress only works for the JSON files in a directory".into(),
+            ));
+        }
+        ford_writer = ford_writer.compressed().map_err(Error::InvalidArguments)?;
+    }
+    if list_opts.format_string.is_some() && is_directory {
+        return Err(Error::InvalidArguments(
+            "--format-string writes a line per emoji, so --output can't be a directory".into(),
+        ));
+    }
+    if list_opts.names_only && matches!(ford_writer, FileOrDirectoryWriter::Directory(..)) {
+        return Err(Error::InvalidArguments(
+            "--names-only writes one name per line, so --output can't be a directory".into(),
+        ));
+    }
+    if list_opts.manifest && !matches!(ford_writer, FileOrDirectoryWriter::Directory(..)) {
+        return Err(Error::InvalidArguments(
+            "--manifest only works when writing to a directory".into(),
+        ));
+    }
+    let output_format = list_opts.output_format;
+    if output_format == OutputFormat::Json
+        && matches!(ford_writer, FileOrDirectoryWriter::StdOut)
+        && database.is_none()
+    {
+        return Err(Error::InvalidArguments(
+            "--output-format json prints events to STDOUT, so --output can't be '-'".into(),
+        ));
+    }
+
+    if list_opts.check_auth {
+        require_auth(client, &list_opts.slack)?;
+    }
+    let emoji = get_emoji_paged(
+        client,
+        &list_opts.slack.workspace,
+        &list_opts.slack.token,
+        list_opts.page_size,
+        global_opts.verbose,
+    )
+    .map_err(|e| Error::Api("Could not get emojis".into(), e))?;
+    // let emoji: Vec<Emoji> = vec![Emoji::new("blub"), Emoji::new("blab")];
+    let name_filter = &list_opts.name_filter;
+    let emoji_filter = &list_opts.emoji_filter;
+    let (mut emoji, filtered): (Vec<Emoji>, Vec<Emoji>) = emoji
+        .into_iter()
+        .partition(|e| name_filter.matches(&e.name) && emoji_filter.matches(e));
+    let before = emoji.len();
+    emoji.retain(|e| list_opts.keeps_kind(e));
+    let kind_skipped = match before - emoji.len() {
+        0 => String::new(),
+        n if list_opts.skip_aliases => format!(" ({} aliases skipped)", n),
+        n => format!(" ({} non-aliases skipped)", n),
+    };
+    let skipped = filtered.len() + before - emoji.len();
+    assign_file_names(&mut emoji);
+    let sort = match list_opts.sort {
+        Some(sort) => sort,
+        None if list_opts.names_only => SortKey::Name,
+        None => SortKey::Created,
+    };
+    sort.sort(&mut emoji, list_opts.reverse);
+    if list_opts.resolve_users {
+        resolve_users(client, &list_opts.slack, &mut emoji, progress);
+    }
+    for e in &emoji {
+        output_format.emit(&Event::Emoji {
+            name: &e.name,
+            url: &e.url,
+        });
+    }
+
+    handle_interrupts();
+    let pb = progress.start(emoji.len() as u64, PROGRESS_TEMPLATE);
+    let mut failed = if list_opts.table {
+        let width = console::Term::stdout()
+            .size_checked()
+            .map(|(_, w)| w as usize);
+        print!("{}", format_table(&emoji, width));
+        0
+    } else if let Some(database) = &database {
+        match write_sqlite(database, &emoji, Timestamp::now()) {
+            Ok(()) => {
+                pb.inc(emoji.len() as u64);
+                0
+            }
+            Err(e) => {
+                pb.println(&format!("Could not update {:?}: {}", database, e));
+                emoji.len()
+            }
+        }
+    } else if let Some(template) = &list_opts.format_string {
+        write_emoji_template(&mut ford_writer, &emoji, template, &pb)
+    } else if format == ListFormat::Ndjson {
+        write_emoji_ndjson(&mut ford_writer, &emoji, &pb)
+    } else if format == ListFormat::Csv {
+        write_emoji_csv(&mut ford_writer, &emoji, &list_opts.columns, &pb)
+    } else if format == ListFormat::Markdown {
+        write_emoji_markdown(&mut ford_writer, &emoji, list_opts.group_by, &pb)
+    } else if format == ListFormat::Emojipacks {
+        let title = workspace_name(&list_opts.slack.workspace);
+        let yaml_dir = list_opts
+            .output
+            .as_deref()
+            .filter(|path| path.as_os_str() != "-")
+            .and_then(std::path::Path::parent)
+            .unwrap_or_else(|| std::path::Path::new(""));
+        let yaml = emojipacks_yaml(&title, &emoji, list_opts.local_src.as_deref(), yaml_dir);
+        match ford_writer.write("emojipacks", yaml.trim_end().to_string()) {
+            Ok(_) => {
+                pb.inc(emoji.len() as u64);
+                0
+            }
+            Err(e) => {
+                pb.println(&format!("Could not write the YAML: {}", e));
+                emoji.len()
+            }
+        }
+    } else if list_opts.names_only {
+        write_emoji_names(&mut ford_writer, &emoji, &pb)
+    } else {
+        write_emoji_metadata(&mut ford_writer, &emoji, &global_opts, output_format, &pb)
+    };
+    match (list_opts.manifest, &ford_writer) {
+        (true, FileOrDirectoryWriter::Directory(dir, _)) => {
+            if let Err(e) = write_manifest(dir, &emoji) {
+                pb.println(&format!("Could not write {}: {}", MANIFEST_FILE, e));
+                failed += 1;
+            }
+        }
+        // an older manifest would hide the files just written from `download`
+        (false, FileOrDirectoryWriter::Directory(dir, _)) if dir.join(MANIFEST_FILE).is_file() => {
+            if let Err(e) = remove_file(dir.join(MANIFEST_FILE)) {
+                pb.println(&format!(
+                    "Could not remove the old {}: {}",
+                    MANIFEST_FILE, e
+                ));
+                failed += 1;
+            }
+        }
+        _ => (),
+    }
+    if interrupted() {
+        pb.abandon_with_message(&format!(
+            "Interrupted after {} of {} emoji",
+            pb.position(),
+            emoji.len()
+        ));
+        return Err(Error::Interrupted);
+    }
+    pb.finish_with_message(&format!(
+        "Done! {} emoji written{}, {} failed, {} of {} filtered out, {} retries",
+        emoji.len() - failed.min(emoji.len()),
+        kind_skipped,
+        failed,
+        filtered.len(),
+        emoji.len() + skipped,
+        client.retries()
+    ));
+    output_format.emit(&Event::Summary {
+        command: "list",
+        total: emoji.len() + skipped,
+        ok: emoji.len().saturating_sub(failed),
+        skipped,
+        failed,
+        bytes: None,
+    });
+
+    let unknown_fields = client.unknown_fields();
+    if list_opts.report_unknown_fields {
+        report_unknown_fields(&unknown_fields, output_format);
+    }
+    if list_opts.strict && !unknown_fields.is_empty() {
+        let names: Vec<&str> = unknown_fields.keys().map(String::as_str).collect();
+        return Err(Error::Failed(format!(
+            "Slack sent unknown fields: {}",
+            names.join(", ")
+        )));
+    }
+    Ok(Summary {
+        failed,
+        allowed_failures: list_opts.max_failures,
+    })
+}
+
+/// Renders emoji as a table for `list --table`, shrinking the widest columns to fit `width`
+fn format_table(emoji: &[Emoji], width: Option<usize>) -> String {
+    const HEADER: [&str; 4] = ["NAME", "CREATOR", "CREATED", "ALIAS FOR"];
+    let rows: Vec<[String; 4]> = emoji
+        .iter()
+        .map(|e| {
+            let (year, month, day) = e.created.date();
+            [
+                e.name.clone(),
+                e.user_display_name.clone(),
+                format!("{:04}-{:02}-{:02}", year, month, day),
+                e.alias_for.clone(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 4];
+    for row in std::iter::once(&HEADER.map(String::from)).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(console::measure_text_width(cell));
+        }
+    }
+    if let Some(width) = width {
+        // the dates are never cut, and the other columns keep a few characters at least
+        let gaps = 2 * (widths.len() - 1);
+        while widths.iter().sum::<usize>() + gaps > width {
+            let widest = (0..widths.len())
+                .filter(|&i| i != 2 && widths[i] > 8)
+                .max_by_key(|&i| widths[i]);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&HEADER.map(String::from)).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| {
+                let cell_width = console::measure_text_width(cell);
+                if cell_width > width {
+                    console::truncate_str(cell, width, "…").into_owned()
+                } else {
+                    format!("{}{}", cell, " ".repeat(width - cell_width))
+                }
+            })
+            .collect();
+        table += cells.join("  ").trim_end();
+        table += "\n";
+    }
+    table
+}
+
+/// The YAML emojipacks reads, with the images of `local_src` where they were downloaded
+///
+/// Aliases have `alias_of` instead of `src`. Strings are written as JSON, which is valid YAML.
+/// Local images are relative to `yaml_dir`, the directory the YAML is written to.
+pub(crate) fn emojipacks_yaml(
+    title: &str,
+    emoji: &[Emoji],
+    local_src: Option<&std::path::Path>,
+    yaml_dir: &std::path::Path,
+) -> String {
+    let quote = |s: &str| serde_json::Value::from(s).to_string();
+    let mut yaml = format!("title: {}\nemojis:\n", quote(title));
+    for e in emoji {
+        yaml += &format!("  - name: {}\n", quote(&e.name));
+        if e.is_alias != 0 {
+            yaml += &format!("    alias_of: {}\n", quote(&e.alias_for));
+            continue;
+        }
+        let src = match local_src.map(|dir| image_path(dir, e)) {
+            Some(path) if path.is_file() => relative_path(&path, yaml_dir)
+                .to_string_lossy()
+                .into_owned(),
+            Some(_) => {
+                log::warn!("{}: No downloaded image, using its URL", e.name);
+                e.url.clone()
+            }
+            None => e.url.clone(),
+        };
+        yaml += &format!("    src: {}\n", quote(&src));
+    }
+    yaml
+}
+
+/// `path` relative to the directory `base`, going up with `..` where needed
+///
+/// Both have to exist. Otherwise, or if they have nothing in common like different drives on
+/// Windows, this is the absolute `path`.
+fn relative_path(path: &std::path::Path, base: &std::path::Path) -> PathBuf {
+    let base = if base.as_os_str().is_empty() {
+        std::path::Path::new(".")
+    } else {
+        base
+    };
+    let (path, base) = match (path.canonicalize(), base.canonicalize()) {
+        (Ok(path), Ok(base)) => (path, base),
+        _ => return std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    };
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path;
+    }
+    let mut relative: PathBuf = base.components().skip(common).map(|_| "..").collect();
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+/// Escapes what Markdown would format in `text`, and the pipes that would end a table cell
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '<' | '>' | '(' | ')' | '#' | '+'
+            | '!' | '|' | '~' | '&' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A Markdown table with the image, name, creator, and creation date of each emoji
+fn markdown_table(emoji: &[&Emoji]) -> String {
+    let mut table =
+        String::from("| Image | Name | Creator | Created |\n| --- | --- | --- | --- |\n");
+    for e in emoji {
+        let image = match e.url.as_str() {
+            "" => String::new(),
+            url => format!(
+                "![]({})",
+                url.replace(' ', "%20")
+                    .replace('(', "%28")
+                    .replace(')', "%29")
+                    .replace('|', "%7C")
+            ),
+        };
+        let name = match e.is_alias {
+            0 => format!(":{}:", markdown_escape(&e.name)),
+            _ => format!(
+                ":{}: (alias of :{}:)",
+                markdown_escape(&e.name),
+                markdown_escape(&e.alias_for)
+            ),
+        };
+        let (year, month, day) = e.created.date();
+        table += &format!(
+            "| {} | {} | {} | {:04}-{:02}-{:02} |\n",
+            image,
+            name,
+            markdown_escape(&e.user_display_name),
+            year,
+            month,
+            day
+        );
+    }
+    table
+}
+
+/// Writes a Markdown table of all emoji, or one per group, for `list --format markdown`
+///
+/// A directory gets a file per group, named after its letter, or `emoji.md` without groups. The
+/// emoji keep their order within each group. Returns how many emoji could not be written.
+fn write_emoji_markdown(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    group_by: Option<GroupBy>,
+    pb: &dyn Progress,
+) -> usize {
+    // the file name, heading, and emoji of each table
+    let tables: Vec<(String, Option<String>, Vec<&Emoji>)> = match group_by {
+        None => vec![("emoji".into(), None, emoji.iter().collect())],
+        Some(group_by) => {
+            let mut groups: std::collections::BTreeMap<Option<char>, Vec<&Emoji>> =
+                std::collections::BTreeMap::new();
+            for e in emoji {
+                groups.entry(group_by.group(e)).or_default().push(e);
+            }
+            groups
+                .into_iter()
+                .map(|(group, emoji)| match group {
+                    Some(c) => (c.to_string(), Some(c.to_uppercase().to_string()), emoji),
+                    None => ("other".into(), Some("Other".into()), emoji),
+                })
+                .collect()
+        }
+    };
+
+    let is_directory = matches!(ford_writer, FileOrDirectoryWriter::Directory(..));
+    let mut failed = 0;
+    for (i, (name, heading, emoji)) in tables.iter().enumerate() {
+        if interrupted() {
+            break;
+        }
+        let mut markdown = String::new();
+        if i > 0 && !is_directory {
+            markdown += "\n";
+        }
+        if let Some(heading) = heading {
+            markdown += &format!("## {}\n\n", heading);
+        }
+        markdown += markdown_table(emoji).trim_end();
+        match ford_writer.write_as(name, "md", markdown) {
+            Ok(_) => pb.inc(emoji.len() as u64),
+            Err(error) => {
+                pb.println(&format!("{}: Could not write: {}", name, error));
+                failed += emoji.len();
+            }
+        }
+    }
+    failed
+}
+
+/// Writes a header and a line for each emoji, for `list --format csv`
+///
+/// Returns how many lines could not be written.
+fn write_emoji_csv(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    columns: &[CsvColumn],
+    pb: &dyn Progress,
+) -> usize {
+    let header = csv_line(columns.iter().map(|c| c.name()));
+    if let Err(error) = ford_writer.write("header", header) {
+        pb.println(&format!("Could not write the CSV header: {}", error));
+        return emoji.len();
+    }
+    let mut failed = 0;
+    for e in track(pb, emoji.iter().take_while(|_| !interrupted())) {
+        let line = csv_line(columns.iter().map(|c| c.value(e)));
+        if let Err(error) = ford_writer.write(&e.name, line) {
+            pb.println(&format!("{}: Could not write: {}", e.name, error));
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Why `list --format sqlite` doesn't work in builds without the `sqlite` feature
+const NO_SQLITE: &str =
+    "This build can't write SQLite databases, install it with --features sqlite";
+
+/// The table `list --format sqlite` keeps emoji in, with when each was first and last listed
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS emoji (
+    name TEXT PRIMARY KEY NOT NULL,
+    is_alias INTEGER NOT NULL,
+    alias_for TEXT NOT NULL,
+    url TEXT NOT NULL,
+    created INTEGER NOT NULL,
+    created_iso8601 TEXT NOT NULL,
+    user_display_name TEXT NOT NULL,
+    avatar_hash TEXT NOT NULL,
+    user_id TEXT,
+    team_id TEXT,
+    user_real_name TEXT,
+    user_email TEXT,
+    image_sha256 TEXT,
+    image_bytes INTEGER,
+    unavailable_fields TEXT NOT NULL,
+    unknown_fields TEXT NOT NULL,
+    first_seen_at INTEGER NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+";
+
+/// Quotes text as an SQL string literal, `NULL` for `None`
+#[cfg(feature = "sqlite")]
+fn sql_text(text: Option<&str>) -> String {
+    match text {
+        Some(text) => format!("'{}'", text.replace('\'', "''")),
+        None => "NULL".to_string(),
+    }
+}
+
+/// The SQL that creates the `emoji` table if needed and upserts all emoji into it
+///
+/// Rows of emoji that weren't listed again are kept, with the `fetched_at` they were last seen.
+#[cfg(feature = "sqlite")]
+fn sqlite_script(emoji: &[Emoji], fetched_at: Timestamp) -> String {
+    let mut script = format!("{}BEGIN;\n", SQLITE_SCHEMA);
+    for e in emoji {
+        let json = |value: serde_json::Value| sql_text(Some(&value.to_string()));
+        let values = [
+            sql_text(Some(&e.name)),
+            e.is_alias.to_string(),
+            sql_text(Some(&e.alias_for)),
+            sql_text(Some(&e.url)),
+            e.created.seconds().to_string(),
+            sql_text(Some(&e.created.iso8601())),
+            sql_text(Some(&e.user_display_name)),
+            sql_text(Some(&e.avatar_hash)),
+            sql_text(e.user_id.as_deref()),
+            sql_text(e.team_id.as_deref()),
+            sql_text(e.user_real_name.as_deref()),
+            sql_text(e.user_email.as_deref()),
+            sql_text(e.image_sha256.as_deref()),
+            e.image_bytes
+                .map_or_else(|| "NULL".to_string(), |bytes| bytes.to_string()),
+            json(serde_json::json!(e.unavailable_fields)),
+            json(serde_json::json!(e.unknown_fields)),
+            fetched_at.seconds().to_string(),
+            fetched_at.seconds().to_string(),
+        ];
+        script += &format!(
+            "INSERT INTO emoji VALUES ({}) ON CONFLICT (name) DO UPDATE SET \
+             is_alias = excluded.is_alias, alias_for = excluded.alias_for, url = excluded.url, \
+             created = excluded.created, created_iso8601 = excluded.created_iso8601, \
+             user_display_name = excluded.user_display_name, \
+             avatar_hash = excluded.avatar_hash, user_id = excluded.user_id, \
+             team_id = excluded.team_id, user_real_name = excluded.user_real_name, \
+             user_email = excluded.user_email, image_sha256 = excluded.image_sha256, \
+             image_bytes = excluded.image_bytes, \
+             unavailable_fields = excluded.unavailable_fields, \
+             unknown_fields = excluded.unknown_fields, fetched_at = excluded.fetched_at;\n",
+            values.join(", ")
+        );
+    }
+    script + "COMMIT;\n"
+}
+
+/// Upserts emoji into the SQLite database at `path` via the sqlite3 command, see `sqlite_script`
+#[cfg(feature = "sqlite")]
+fn write_sqlite(
+    path: &std::path::Path,
+    emoji: &[Emoji],
+    fetched_at: Timestamp,
+) -> Result<(), String> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut child = Command::new("sqlite3")
+        .arg("-bail")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run sqlite3: {}", e))?;
+    let script = sqlite_script(emoji, fetched_at);
+    let written = child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(script.as_bytes());
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("sqlite3 failed: {}", e))?;
+    match (output.status.success(), written) {
+        (true, Ok(())) => Ok(()),
+        (true, Err(e)) => Err(format!("Could not send the data to sqlite3: {}", e)),
+        (false, _) => Err(format!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn write_sqlite(
+    _path: &std::path::Path,
+    _emoji: &[Emoji],
+    _fetched_at: Timestamp,
+) -> Result<(), String> {
+    Err(NO_SQLITE.to_string())
+}
+
+/// Writes a line per emoji for `list --format-string`
+///
+/// Returns how many lines could not be written.
+fn write_emoji_template(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    template: &Template,
+    pb: &dyn Progress,
+) -> usize {
+    let mut failed = 0;
+    for e in track(pb, emoji.iter().take_while(|_| !interrupted())) {
+        if let Err(error) = ford_writer.write(&e.name, template.render(e, None)) {
+            pb.println(&format!("{}: Could not write: {}", e.name, error));
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Writes the compact JSON of each emoji on a line of its own, for `list --format ndjson`
+///
+/// Returns how many emoji could not be written.
+fn write_emoji_ndjson(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    pb: &dyn Progress,
+) -> usize {
+    let mut failed = 0;
+    for e in track(pb, emoji.iter().take_while(|_| !interrupted())) {
+        let written = serde_json::to_string(e)
+            .map_err(|error| error.to_string())
+            .and_then(|line| {
+                ford_writer
+                    .write(&e.name, line)
+                    .map_err(|error| error.to_string())
+            });
+        if let Err(error) = written {
+            pb.println(&format!("{}: Could not write: {}", e.name, error));
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Writes the name of each emoji on a line of its own, for `list --names-only`
+///
+/// Returns how many names could not be written.
+fn write_emoji_names(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    pb: &dyn Progress,
+) -> usize {
+    let mut failed = 0;
+    for e in track(pb, emoji.iter().take_while(|_| !interrupted())) {
+        if let Err(error) = ford_writer.write(&e.name, e.name.clone()) {
+            pb.println(&format!("{}: Could not write: {}", e.name, error));
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Writes the JSON metadata of each emoji, reporting failures on the progress bar
+///
+/// Returns how many emoji could not be written.
+pub(crate) fn write_emoji_metadata(
+    ford_writer: &mut FileOrDirectoryWriter,
+    emoji: &[Emoji],
+    global_opts: &GlobalOptions,
+    output_format: OutputFormat,
+    pb: &dyn Progress,
+) -> usize {
+    let mut failed = 0;
+    for e in track(pb, emoji.iter().take_while(|_| !interrupted())) {
+        if global_opts.verbose {
+            pb.println(&format!("{} -> {} ({})", e.name, e.url, e.created));
+            if !e.created.is_plausible() && !e.unavailable_fields.iter().any(|f| f == "created") {
+                pb.println(&format!(
+                    "{}: Suspicious creation time {}, keeping it as is",
+                    e.name,
+                    e.created.seconds()
+                ));
+            }
+        }
+        match serde_json::to_string_pretty(e) {
+            Ok(s) => match ford_writer.write(&e.file_name(), s) {
+                Ok(_) => {
+                    if let Some(path) = ford_writer.file_path(&e.file_name()) {
+                        output_format.emit(&Event::Written {
+                            name: &e.name,
+                            path: &path,
+                        });
+                    }
+                }
+                Err(error) => {
+                    pb.println(&format!("{}: Could not write: {}", e.name, error));
+                    failed += 1;
+                }
+            },
+            Err(error) => {
+                pb.println(&format!(
+                    "{}: Could not serialize: {}: {:?}",
+                    e.name, error, e
+                ));
+                failed += 1;
+            }
+        };
+    }
+    failed
+}
+
+/// Adds the real name and email of their creators to emoji, looking up each user once
+///
+/// Lookups that fail leave the emoji with just the user ID.
+fn resolve_users(
+    client: &RetryingClient,
+    slack: &WorkspaceOptions,
+    emoji: &mut [Emoji],
+    progress: ProgressMode,
+) {
+    let user_ids: std::collections::BTreeSet<String> =
+        emoji.iter().filter_map(|e| e.user_id.clone()).collect();
+    let pb = progress.start(user_ids.len() as u64, PROGRESS_TEMPLATE);
+    pb.set_prefix("users");
+    let mut users = std::collections::HashMap::new();
+    for user_id in track(&pb, user_ids.into_iter()) {
+        match user_info(client, &slack.workspace, &slack.token, &user_id) {
+            Ok(user) => {
+                users.insert(user_id, user);
+            }
+            Err(e) => pb.println(&format!("Could not look up user {}: {}", user_id, e)),
+        }
+    }
+    pb.finish_and_clear();
+
+    for e in emoji {
+        if let Some(user) = e.user_id.as_ref().and_then(|id| users.get(id)) {
+            e.user_real_name = user.real_name.clone();
+            e.user_email = user.email.clone();
+        }
+    }
+}
+
+/// Prints each unknown field with its example value, or emits them as events
+fn report_unknown_fields(fields: &UnknownJSONFields, output_format: OutputFormat) {
+    if output_format == OutputFormat::Json {
+        for (field, example) in fields {
+            output_format.emit(&Event::UnknownField { field, example });
+        }
+        return;
+    }
+    if fields.is_empty() {
+        eprintln!("No unknown fields");
+        return;
+    }
+    eprintln!("Unknown fields ({}):", fields.len());
+    for (field, example) in fields {
+        eprintln!("  {}: {}", field, redact(&example_value(example)));
+    }
+}
+
+/// The compact JSON of `value`, shortened if it's long
+pub(crate) fn example_value(value: &serde_json::Value) -> String {
+    let json = value.to_string();
+    match json.char_indices().nth(60) {
+        Some((end, _)) => format!("{}...", &json[..end]),
+        None => json,
+    }
+}
+
+#[cfg(test)]
+mod resolve_users_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer};
+    use structopt::StructOpt;
+
+    #[test]
+    fn looks_up_each_user_once() {
+        let server = MockServer::start(vec![
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "user": {
+                    "id": "U1",
+                    "profile": {"real_name": "Robin M", "email": "robin@example.com"},
+                },
+            })),
+            MockResponse::json(serde_json::json!({"ok": false, "error": "user_not_found"})),
+        ]);
+        let mut emoji: Vec<Emoji> = ["parrot", "blob", "gone"]
+            .iter()
+            .map(|name| Emoji::new(name))
+            .collect();
+        emoji[0].user_id = Some("U1".into());
+        emoji[1].user_id = Some("U1".into());
+        emoji[2].user_id = Some("U2".into());
+        let args = [
+            "slack-emoji",
+            "list",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+        ];
+        let slack = match Cli::from_iter_safe(args).unwrap().command {
+            Commands::List(list_opts) => list_opts.slack,
+            command => panic!("not a list command: {:?}", command),
+        };
+
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        resolve_users(&client, &slack, &mut emoji, ProgressMode::None);
+        assert_eq!(
+            server.finish(),
+            vec!["POST /api/users.info", "POST /api/users.info"]
+        );
+        for e in &emoji[..2] {
+            assert_eq!(e.user_real_name.as_deref(), Some("Robin M"));
+            assert_eq!(e.user_email.as_deref(), Some("robin@example.com"));
+        }
+        // a failed lookup leaves just the ID
+        assert_eq!(emoji[2].user_id.as_deref(), Some("U2"));
+        assert_eq!(
+            (&emoji[2].user_real_name, &emoji[2].user_email),
+            (&None, &None)
+        );
+    }
+}
+
+#[cfg(test)]
+mod list_options_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+    use structopt::StructOpt;
+
+    fn names(emoji: &[Emoji]) -> Vec<&str> {
+        emoji.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_keys() {
+        let mut emoji: Vec<Emoji> = [
+            ("b", 3, "zoe"),
+            ("A", 2, "zoe"),
+            ("c", 1, "amy"),
+            ("a", 1, "zoe"),
+        ]
+        .iter()
+        .map(|(name, created, user)| {
+            let mut e = Emoji::new(name);
+            e.created = Timestamp(*created);
+            e.user_display_name = user.to_string();
+            e
+        })
+        .collect();
+
+        SortKey::Created.sort(&mut emoji, false);
+        assert_eq!(names(&emoji), vec!["a", "c", "A", "b"]);
+        SortKey::Name.sort(&mut emoji, false);
+        assert_eq!(names(&emoji), vec!["A", "a", "b", "c"]);
+        SortKey::User.sort(&mut emoji, false);
+        assert_eq!(names(&emoji), vec!["c", "A", "a", "b"]);
+        SortKey::Name.sort(&mut emoji, true);
+        assert_eq!(names(&emoji), vec!["c", "b", "a", "A"]);
+    }
+
+    fn list_options(args: &[&str]) -> Result<ListOptions, structopt::clap::Error> {
+        let args = [
+            "slack-emoji",
+            "list",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+        ]
+        .iter()
+        .chain(args);
+        match Cli::from_iter_safe(args)?.command {
+            Commands::List(list_opts) => Ok(list_opts),
+            command => panic!("not a list command: {:?}", command),
+        }
+    }
+
+    #[test]
+    fn alias_flags() {
+        let mut alias = Emoji::new("party");
+        alias.is_alias = 1;
+        let emoji = Emoji::new("parrot");
+
+        let all = list_options(&[]).unwrap();
+        assert!(all.keeps_kind(&alias) && all.keeps_kind(&emoji));
+        let skip = list_options(&["--skip-aliases"]).unwrap();
+        assert!(!skip.keeps_kind(&alias) && skip.keeps_kind(&emoji));
+        let only = list_options(&["--only-aliases"]).unwrap();
+        assert!(only.keeps_kind(&alias) && !only.keeps_kind(&emoji));
+
+        let both = list_options(&["--skip-aliases", "--only-aliases"]).unwrap_err();
+        assert_eq!(both.kind, structopt::clap::ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn names_only() {
+        let dir = TestDir::new("names-only-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("names.txt");
+        let mut writer: FileOrDirectoryWriter = path.clone().try_into().unwrap();
+        let emoji = vec![Emoji::new("blob"), Emoji::new("parrot")];
+        let failed = write_emoji_names(&mut writer, &emoji, &NoProgress::default());
+        drop(writer);
+        assert_eq!(failed, 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "blob\nparrot\n");
+
+        assert!(list_options(&["--names-only"]).unwrap().pipes_names());
+        assert!(list_options(&["--names-only", "--output", "-"])
+            .unwrap()
+            .pipes_names());
+        assert!(!list_options(&["--names-only", "--output", "names.txt"])
+            .unwrap()
+            .pipes_names());
+        assert!(list_options(&["--names-only", "--manifest"]).is_err());
+    }
+
+    #[test]
+    fn table() {
+        let mut parrot = Emoji::new("partyparrot");
+        parrot.created = Timestamp(1717243199);
+        let mut alias = Emoji::new("pp");
+        alias.alias_for = "partyparrot".into();
+        alias.user_display_name = "Robin Example".into();
+        alias.created = Timestamp(1717243199);
+        let emoji = vec![parrot, alias];
+
+        assert_eq!(
+            format_table(&emoji, None),
+            "NAME         CREATOR        CREATED     ALIAS FOR\n\
+             partyparrot  M3t0r          2024-06-01\n\
+             pp           Robin Example  2024-06-01  partyparrot\n"
+        );
+        assert_eq!(
+            format_table(&emoji, Some(40)),
+            "NAME      CREATOR   CREATED     ALIAS F…\n\
+             partypa…  M3t0r     2024-06-01\n\
+             pp        Robin E…  2024-06-01  partypa…\n"
+        );
+        assert!(list_options(&["--table", "--output", "x"]).is_err());
+        assert!(list_options(&["--count-only", "--names-only"]).is_err());
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(csv_line(["a", "b c", ""].iter()), "a,b c,");
+        assert_eq!(
+            csv_line(["Smith, Robin", "say \"hi\"", "two\nlines"].iter()),
+            "\"Smith, Robin\",\"say \"\"hi\"\"\",\"two\nlines\""
+        );
+
+        let dir = TestDir::new("csv-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("emoji.csv");
+        let mut writer: FileOrDirectoryWriter = path.clone().try_into().unwrap();
+        let mut alias = Emoji::new("pp");
+        alias.is_alias = 1;
+        alias.alias_for = "partyparrot".into();
+        alias.user_display_name = "Example, Robin".into();
+        alias.created = Timestamp(1717243199);
+        let columns = list_options(&[
+            "--format",
+            "csv",
+            "--columns",
+            "name,created,user_display_name,is_alias",
+        ])
+        .unwrap()
+        .columns;
+        let failed = write_emoji_csv(&mut writer, &[alias], &columns, &NoProgress::default());
+        drop(writer);
+        assert_eq!(failed, 0);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "name,created,user_display_name,is_alias\n\
+             pp,2024-06-01T11:59:59Z,\"Example, Robin\",true\n"
+        );
+
+        assert_eq!(list_options(&[]).unwrap().columns.len(), 6);
+        assert!(list_options(&["--columns", "name,size"]).is_err());
+    }
+
+    #[test]
+    fn emojipacks() {
+        let dir = TestDir::new("emojipacks-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        std::fs::write(dir.path.join("parrot.gif"), b"GIF89a").unwrap();
+        let mut parrot = Emoji::new("parrot");
+        parrot.url = "https://emoji.slack-edge.com/T1/parrot/abc.gif".into();
+        let mut alias = Emoji::new("party \"parrot\"");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+        let emoji = vec![parrot, alias, Emoji::new("missing")];
+
+        assert_eq!(
+            emojipacks_yaml("acme", &emoji[..2], None, std::path::Path::new("")),
+            "title: \"acme\"\nemojis:\n\
+             \x20 - name: \"parrot\"\n\
+             \x20   src: \"https://emoji.slack-edge.com/T1/parrot/abc.gif\"\n\
+             \x20 - name: \"party \\\"parrot\\\"\"\n\
+             \x20   alias_of: \"parrot\"\n"
+        );
+        let local = emojipacks_yaml("acme", &emoji, Some(dir.path), std::path::Path::new(""));
+        assert!(local.contains("src: \"emojipacks-test-dir/parrot.gif\""));
+        assert!(local.contains("src: \"https://cdn.example.com/emoji.png\""));
+        assert!(list_options(&["--format", "emojipacks"]).is_ok());
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            markdown_escape("a|b *c* [d](e) 1_2\nf"),
+            "a\\|b \\*c\\* \\[d\\]\\(e\\) 1\\_2 f"
+        );
+
+        let mut parrot = Emoji::new("parrot");
+        parrot.url = "https://emoji.slack-edge.com/T1/parrot/a(b).gif".into();
+        parrot.user_display_name = "Robin | Ops".into();
+        parrot.created = Timestamp(1717243199);
+        let mut alias = Emoji::new("Party_parrot");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+        alias.url = String::new();
+        alias.created = Timestamp(0);
+        let emoji = vec![alias, parrot, Emoji::new("+1")];
+        assert_eq!(
+            markdown_table(&emoji[..2].iter().collect::<Vec<_>>()),
+            "| Image | Name | Creator | Created |\n| --- | --- | --- | --- |\n\
+             |  | :Party\\_parrot: (alias of :parrot:) | M3t0r | 1970-01-01 |\n\
+             | ![](https://emoji.slack-edge.com/T1/parrot/a%28b%29.gif) | :parrot: | \
+             Robin \\| Ops | 2024-06-01 |\n"
+        );
+
+        let dir = TestDir::new("markdown-test-dir");
+        let mut writer: FileOrDirectoryWriter =
+            PathBuf::from("markdown-test-dir/").try_into().unwrap();
+        let failed = write_emoji_markdown(
+            &mut writer,
+            &emoji,
+            Some(GroupBy::Letter),
+            &NoProgress::default(),
+        );
+        assert_eq!(failed, 0);
+        let p = std::fs::read_to_string(dir.path.join("p.md")).unwrap();
+        assert!(p.starts_with("## P\n\n| Image |"));
+        // sorted as given, regardless of case
+        assert!(p.find(":Party").unwrap() < p.find(":parrot:").unwrap());
+        assert!(std::fs::read_to_string(dir.path.join("other.md"))
+            .unwrap()
+            .contains(":\\+1:"));
+
+        let path = dir.path.join("emoji.md");
+        let mut writer: FileOrDirectoryWriter = path.clone().try_into().unwrap();
+        write_emoji_markdown(&mut writer, &emoji, None, &NoProgress::default());
+        drop(writer);
+        assert_eq!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .matches("| Image |")
+                .count(),
+            1
+        );
+
+        assert_eq!(
+            list_options(&["--format", "markdown", "--group-by", "letter"])
+                .unwrap()
+                .group_by,
+            Some(GroupBy::Letter)
+        );
+        assert!(list_options(&["--group-by", "user"]).is_err());
+    }
+
+    #[test]
+    fn format_string() {
+        let mut parrot = Emoji::new("parrot");
+        parrot.created = Timestamp(1717243199);
+        parrot.user_id = Some("U1".into());
+        let template: Template = "{name}\\t{created_iso8601}\\t{user_id}{team_id} {{x}}\\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            template.render(&parrot, None),
+            "parrot\t2024-06-01T11:59:59Z\tU1 {x}\n"
+        );
+        assert!(!template.shows_download());
+
+        let path = PathBuf::from("emoji/parrot.gif");
+        let event = Event::Download {
+            name: "parrot",
+            status: EventStatus::Skipped,
+            path: Some(&path),
+            bytes: None,
+        };
+        let template: Template = "{status} {path} {image_bytes}".parse().unwrap();
+        assert_eq!(
+            template.render(&parrot, Some(&event)),
+            "skipped emoji/parrot.gif "
+        );
+        assert!(template.shows_download());
+
+        let unknown = "{name} {size}".parse::<Template>().unwrap_err();
+        assert!(unknown.contains("{size}"));
+        assert!(unknown.contains("{created_iso8601}"));
+        assert!("{name".parse::<Template>().is_err());
+        assert!("}".parse::<Template>().is_err());
+
+        assert!(list_options(&["--format-string", "{name}"]).is_ok());
+        assert!(list_options(&["--format-string", "{nmae}"]).is_err());
+        assert!(list_options(&["--format-string", "{name}", "--format", "csv"]).is_err());
+    }
+
+    #[test]
+    fn ndjson() {
+        let dir = TestDir::new("ndjson-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let path = dir.path.join("emoji.ndjson");
+        let mut writer: FileOrDirectoryWriter = path.clone().try_into().unwrap();
+        let emoji = vec![Emoji::new("blob"), Emoji::new("parrot")];
+        let failed = write_emoji_ndjson(&mut writer, &emoji, &NoProgress::default());
+        drop(writer);
+        assert_eq!(failed, 0);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let _: Emoji = serde_json::from_str(line).expect("each line is an emoji");
+        }
+        // download reads it like a manifest
+        let read = parse_emoji_manifest(&content).unwrap();
+        assert_eq!(read[1].name, "parrot");
+
+        assert_eq!(list_options(&[]).unwrap().format, None);
+        assert_eq!(
+            list_options(&["--format", "ndjson"]).unwrap().format,
+            Some(ListFormat::Ndjson)
+        );
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected_while_parsing() {
+        let args = |sort: &str| {
+            Cli::from_iter_safe(&[
+                "slack-emoji",
+                "list",
+                "--workspace",
+                "acme",
+                "--token",
+                "xoxs-1",
+                "--sort",
+                sort,
+            ])
+        };
+        assert!(args("user").is_ok());
+        assert!(args("size").is_err());
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod sqlite_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    fn query(database: &std::path::Path, sql: &str) -> String {
+        let output = std::process::Command::new("sqlite3")
+            .arg(database)
+            .arg(sql)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn quotes_text() {
+        assert_eq!(sql_text(Some("it's")), "'it''s'");
+        assert_eq!(sql_text(None), "NULL");
+    }
+
+    #[test]
+    fn upserts_by_name() {
+        let dir = TestDir::new("sqlite-test-dir");
+        std::fs::create_dir_all(dir.path).unwrap();
+        let database = dir.path.join("emoji.db");
+        let mut parrot = Emoji::new("parrot");
+        parrot.created = Timestamp(100);
+        parrot
+            .unknown_fields
+            .insert("is_bad".into(), serde_json::json!(false));
+        let mut alias = Emoji::new("party");
+        alias.is_alias = 1;
+        alias.alias_for = "parrot".into();
+
+        write_sqlite(&database, &[parrot, alias], Timestamp(1000)).unwrap();
+        let mut parrot = Emoji::new("parrot");
+        parrot
+            .unknown_fields
+            .insert("is_bad".into(), serde_json::json!(false));
+        parrot.user_display_name = "O'Brien".into();
+        write_sqlite(&database, &[parrot], Timestamp(2000)).unwrap();
+
+        assert_eq!(
+            query(
+                &database,
+                "SELECT name, is_alias, alias_for, user_display_name, unknown_fields, \
+                 first_seen_at, fetched_at FROM emoji ORDER BY name"
+            ),
+            "parrot|0||O'Brien|{\"is_bad\":false}|1000|2000\n\
+             party|1|parrot|M3t0r|{}|1000|1000\n"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! The subcommands, one module each
+
+use std::io::Write;
+
+mod alias;
+mod analyze;
+mod audit;
+mod auth;
+mod backup;
+mod check;
+mod completions;
+mod copy;
+mod dedupe;
+mod delete;
+mod diff;
+mod download;
+mod history;
+mod import;
+mod info;
+mod list;
+mod policy;
+mod prune;
+mod rename;
+mod serve;
+mod snapshot;
+mod sprite;
+mod stats;
+mod sync;
+mod top;
+mod upload;
+mod verify;
+mod whoami;
+
+pub(crate) use alias::*;
+pub(crate) use analyze::*;
+pub(crate) use audit::*;
+pub(crate) use auth::*;
+pub(crate) use backup::*;
+pub(crate) use check::*;
+pub(crate) use completions::*;
+pub(crate) use copy::*;
+pub(crate) use dedupe::*;
+pub(crate) use delete::*;
+pub(crate) use diff::*;
+pub(crate) use download::*;
+pub(crate) use history::*;
+pub(crate) use import::*;
+pub(crate) use info::*;
+pub(crate) use list::*;
+pub(crate) use policy::*;
+pub(crate) use prune::*;
+pub(crate) use rename::*;
+pub(crate) use serve::*;
+pub(crate) use snapshot::*;
+pub(crate) use sprite::*;
+pub(crate) use stats::*;
+pub(crate) use sync::*;
+pub(crate) use top::*;
+pub(crate) use upload::*;
+pub(crate) use verify::*;
+pub(crate) use whoami::*;
+
+/// Asks a yes/no question on STDERR, defaulting to no
+pub(crate) fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush().ok();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Reads a line from STDIN without echoing it if STDIN is a terminal
+#[cfg(feature = "keyring")]
+pub(crate) fn prompt_hidden(prompt: &str) -> std::io::Result<String> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush().ok();
+
+    #[cfg(unix)]
+    let restore = {
+        // SAFETY: termios is plain old data and only used with the STDIN file descriptor
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::isatty(libc::STDIN_FILENO) == 1
+                && libc::tcgetattr(libc::STDIN_FILENO, &mut termios) == 0
+            {
+                let original = termios;
+                termios.c_lflag &= !libc::ECHO;
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+                Some(original)
+            } else {
+                None
+            }
+        }
+    };
+
+    let mut line = String::new();
+    let read = std::io::stdin().read_line(&mut line);
+
+    #[cfg(unix)]
+    if let Some(original) = restore {
+        // SAFETY: restores the settings read above
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+        eprintln!(); // the newline wasn't echoed either
+    }
+
+    read.map(|_| line.trim().to_string())
+}
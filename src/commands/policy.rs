@@ -0,0 +1,128 @@
+//! `policy`
+
+use crate::*;
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PolicyRulesFile {
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    max_length: Option<usize>,
+}
+
+#[derive(Debug)]
+struct PolicyRules {
+    allow: Vec<regex::Regex>,
+    deny: Vec<regex::Regex>,
+    max_length: Option<usize>,
+}
+
+impl std::str::FromStr for PolicyRules {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: PolicyRulesFile = toml::from_str(s).map_err(|e| e.to_string())?;
+        let compile = |patterns: Vec<String>| {
+            patterns
+                .iter()
+                .map(|p| regex::Regex::new(p).map_err(|e| e.to_string()))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(PolicyRules {
+            allow: compile(file.allow)?,
+            deny: compile(file.deny)?,
+            max_length: file.max_length,
+        })
+    }
+}
+
+impl PolicyRules {
+    /// All the rules a name violates, described for humans
+    pub fn violations(&self, name: &str) -> Vec<String> {
+        let mut violations = vec![];
+        if !self.allow.is_empty() && !self.allow.iter().any(|r| r.is_match(name)) {
+            violations.push("doesn't match any allow rule".to_string());
+        }
+        for rule in self.deny.iter().filter(|r| r.is_match(name)) {
+            violations.push(format!("matches deny rule {:?}", rule.as_str()));
+        }
+        match self.max_length {
+            Some(max) if name.chars().count() > max => {
+                violations.push(format!("is longer than {} characters", max))
+            }
+            _ => (),
+        }
+        violations
+    }
+}
+
+pub(crate) fn policy(
+    client: &RetryingClient,
+    policy_opts: PolicyOptions,
+) -> Result<Summary, Error> {
+    let rules_path = &policy_opts.rules;
+
+    let rules: PolicyRules = std::fs::read_to_string(rules_path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| content.parse())
+        .map_err(|e| {
+            Error::InvalidArguments(format!("Could not load rules from {:?}: {}", rules_path, e))
+        })?;
+
+    let emoji = policy_opts.source.load(client)?;
+    log::debug!("Checking {} emoji", emoji.len());
+
+    let mut violating = 0;
+    for e in &emoji {
+        let mut violations = rules.violations(&e.name);
+        if policy_opts.fix_aliases && e.is_alias != 0 {
+            violations.extend(
+                rules
+                    .violations(&e.alias_for)
+                    .into_iter()
+                    .map(|v| format!("points to {} which {}", e.alias_for, v)),
+            );
+        }
+        for violation in &violations {
+            println!("{}: {}", e.name, violation);
+        }
+        if !violations.is_empty() {
+            violating += 1;
+        }
+    }
+
+    if violating > 0 {
+        log::info!("{} of {} emoji violate the rules", violating, emoji.len());
+    }
+    Ok(Summary::failed(violating))
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rules: PolicyRules =
+            "allow = [\"^team-\", \"^blob\"]\ndeny = [\"^[0-9]\", \"--\"]\nmax_length = 10\n"
+                .parse()
+                .expect("could not parse rules");
+
+        assert!(rules.violations("team-a").is_empty());
+        assert_eq!(
+            rules.violations("parrot"),
+            vec!["doesn't match any allow rule"]
+        );
+        assert_eq!(
+            rules.violations("team--very-long"),
+            vec!["matches deny rule \"--\"", "is longer than 10 characters"]
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!("deny = [\"(\"]".parse::<PolicyRules>().is_err());
+        assert!("typo = 1".parse::<PolicyRules>().is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! `prune`
+
+use crate::*;
+use std::fs::remove_file;
+
+/// Removes the metadata and image files of emoji from a directory
+///
+/// Returns how many emoji had files that could not be removed.
+pub(crate) fn remove_local_emoji(
+    base_path: &std::path::Path,
+    emoji: &[&Emoji],
+    keep_images: bool,
+) -> usize {
+    let mut failed = 0;
+    for e in emoji {
+        let mut removed = true;
+        let json_path = base_path.join(e.file_name());
+        let mut paths = vec![
+            json_path.with_extension("json"),
+            json_path.with_extension(COMPRESSED_JSON_EXTENSION),
+        ];
+        if !keep_images {
+            paths.push(image_path(base_path, e));
+            paths.push(CacheValidators::path(&image_path(base_path, e)));
+        }
+        for path in paths.iter().filter(|path| path.is_file()) {
+            log::debug!("Removing {:?}", path);
+            if let Err(error) = remove_file(path) {
+                log::error!("Could not remove {:?}: {}", path, error);
+                removed = false;
+            }
+        }
+        if !removed {
+            failed += 1;
+        }
+    }
+    failed
+}
+
+pub(crate) fn prune(client: &RetryingClient, prune_opts: PruneOptions) -> Result<Summary, Error> {
+    let slack = prune_opts.slack;
+    let base_path = &prune_opts.path;
+
+    let local: Vec<Emoji> = read_emoji_dir(base_path)
+        .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+        .collect();
+    let remote = get_emoji_names(client, &slack)?;
+
+    let mut stale: Vec<&Emoji> = local
+        .iter()
+        .filter(|e| !remote.contains(&normalize_emoji_name(&e.name)))
+        .collect();
+    stale.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if !prune_opts.delete {
+        for e in &stale {
+            let image = image_path(base_path, e);
+            println!("{}", metadata_path(&image).display());
+            if !prune_opts.keep_images && image_path(base_path, e).is_file() {
+                println!("{}", image_path(base_path, e).display());
+            }
+        }
+        log::info!(
+            "{} emoji no longer exist in {}, use --delete to remove them",
+            stale.len(),
+            slack.workspace
+        );
+        return Ok(Summary::done());
+    }
+
+    let failed = remove_local_emoji(base_path, &stale, prune_opts.keep_images);
+    log::info!("Removed {} emoji", stale.len() - failed);
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod prune_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    #[test]
+    fn keep_images() {
+        let dir = TestDir::new("prune-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let (a, b) = (Emoji::new("a"), Emoji::new("b"));
+        for name in &[
+            "a.json",
+            "a.png",
+            "b.json",
+            "b.png",
+            "b.png.etag",
+            "README.md",
+        ] {
+            std::fs::write(dir.path.join(name), b"test").unwrap();
+        }
+
+        assert_eq!(remove_local_emoji(dir.path, &[&a], true), 0);
+        assert!(!dir.path.join("a.json").exists());
+        assert!(dir.path.join("a.png").exists());
+
+        assert_eq!(remove_local_emoji(dir.path, &[&b], false), 0);
+        assert!(!dir.path.join("b.json").exists());
+        assert!(!dir.path.join("b.png").exists());
+        assert!(!dir.path.join("b.png.etag").exists());
+        assert!(dir.path.join("README.md").exists());
+    }
+}
@@ -0,0 +1,215 @@
+//! `rename`
+
+use crate::*;
+
+pub(crate) fn rename(
+    client: &RetryingClient,
+    rename_opts: RenameOptions,
+) -> Result<Summary, Error> {
+    let slack = rename_opts.slack;
+    let (old_name, new_name) = (&rename_opts.old_name, &rename_opts.new_name);
+
+    let fail = |what: &str| {
+        let what = what.to_string();
+        move |e| Error::Api(what, e)
+    };
+
+    let emoji =
+        get_emoji(client, &slack.workspace, &slack.token).map_err(fail("Could not get emojis"))?;
+
+    let old = match emoji.iter().find(|e| &e.name == old_name) {
+        Some(old) => old,
+        None => {
+            return Err(Error::Failed(format!(
+                "No emoji named {} in {}",
+                old_name, slack.workspace
+            )))
+        }
+    };
+    if emoji.iter().any(|e| &e.name == new_name) {
+        return Err(Error::Failed(format!(
+            "There is already an emoji named {}",
+            new_name
+        )));
+    }
+
+    if old.is_alias != 0 {
+        // aliases have no image of their own, just point the new name at the same target
+        log::debug!("{} is an alias for {}", old_name, old.alias_for);
+        add_alias(
+            client,
+            &slack.workspace,
+            &slack.token,
+            new_name,
+            &old.alias_for,
+        )
+        .map_err(fail("Could not add alias"))?;
+    } else {
+        log::debug!("Downloading {}", old.url);
+        let image = download_image(client, &old.url)
+            .map_err(|e| Error::Api(format!("Could not download {}", old.url), e.into()))?;
+        add_emoji(client, &slack.workspace, &slack.token, new_name, image)
+            .map_err(fail("Could not add emoji"))?;
+    }
+
+    let emoji = get_emoji(client, &slack.workspace, &slack.token)
+        .map_err(fail("Could not verify new emoji"))?;
+    if !emoji.iter().any(|e| &e.name == new_name) {
+        return Err(Error::Failed(format!(
+            "{} was added but doesn't show up in the emoji list, keeping {}",
+            new_name, old_name
+        )));
+    }
+    log::info!("Added {}", new_name);
+
+    if rename_opts.keep_old {
+        return Ok(Summary::done());
+    }
+
+    let mut failed = 0;
+    for alias in emoji
+        .iter()
+        .filter(|e| e.is_alias != 0 && &e.alias_for == old_name)
+    {
+        log::debug!("Re-pointing alias {} to {}", alias.name, new_name);
+        let repointed = replace_emoji(client, &slack, alias, || {
+            add_alias(
+                client,
+                &slack.workspace,
+                &slack.token,
+                &alias.name,
+                new_name,
+            )
+        });
+        if let Err(e) = repointed {
+            log::error!("{}: Could not re-point alias: {}", alias.name, e);
+            failed += 1;
+        }
+    }
+
+    remove_emoji(client, &slack.workspace, &slack.token, old_name)
+        .map_err(fail("Could not remove old emoji"))?;
+    log::info!("Removed {}", old_name);
+
+    Ok(Summary::failed(failed))
+}
+
+/// Replaces an emoji with what `add` adds under the same name
+///
+/// Slack has no way to change an emoji, and names are unique, so it's removed first. If `add`
+/// fails, `old` is put back, and the error tells whether that failed too and it's gone.
+pub(crate) fn replace_emoji(
+    client: &RetryingClient,
+    slack: &WorkspaceOptions,
+    old: &Emoji,
+    add: impl FnOnce() -> Result<(), slack_emoji::Error>,
+) -> Result<(), String> {
+    let (workspace, token) = (&slack.workspace, &slack.token);
+    let image = match old.is_alias {
+        0 => Some(download_image(client, &old.url).map_err(|e| {
+            format!(
+                "Could not download its image to put it back if needed: {}",
+                e
+            )
+        })?),
+        _ => None,
+    };
+    remove_emoji(client, workspace, token, &old.name)
+        .map_err(|e| format!("Could not remove it: {}", e))?;
+    let error = match add() {
+        Ok(()) => return Ok(()),
+        Err(error) => error,
+    };
+    let restored = match image {
+        Some(image) => add_emoji(client, workspace, token, &old.name, image),
+        None => add_alias(client, workspace, token, &old.name, &old.alias_for),
+    };
+    match restored {
+        Ok(()) => Err(format!("{}, put the previous one back", error)),
+        Err(restore_error) => Err(format!(
+            "{}, and putting the previous one back failed too, it's gone: {}",
+            error, restore_error
+        )),
+    }
+}
+
+#[cfg(test)]
+mod rename_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer};
+    use structopt::StructOpt;
+
+    fn rename_options(args: &[&str]) -> RenameOptions {
+        let base = [
+            "slack-emoji",
+            "rename",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+        ];
+        match Cli::from_iter_safe(base.iter().chain(args))
+            .unwrap()
+            .command
+        {
+            Commands::Rename(rename_opts) => rename_opts,
+            command => panic!("not a rename command: {:?}", command),
+        }
+    }
+
+    #[test]
+    fn puts_back_aliases_that_could_not_be_repointed() {
+        let images = MockServer::start(vec![MockResponse {
+            status: 200,
+            headers: vec![("Content-Type", "image/gif".into())],
+            body: b"GIF89a".to_vec(),
+        }]);
+        let url = format!("{}/parrot.gif", images.url);
+        let emoji = |new: bool| {
+            let mut emoji = vec![
+                serde_json::json!({"name": "parrot", "url": url, "created": 1}),
+                serde_json::json!({"name": "party", "url": "alias:parrot", "created": 2,
+                    "is_alias": 1, "alias_for": "parrot"}),
+            ];
+            if new {
+                emoji.push(serde_json::json!({"name": "parrot2", "url": url, "created": 3}));
+            }
+            MockResponse::json(serde_json::json!({
+                "ok": true,
+                "custom_emoji_total_count": emoji.len(),
+                "paging": {"count": 1000, "page": 1, "pages": 1},
+                "emoji": emoji,
+            }))
+        };
+        let ok = || MockResponse::json(serde_json::json!({"ok": true}));
+        let server = MockServer::start(vec![
+            emoji(false),
+            ok(),
+            emoji(true),
+            // removing the alias works, adding it for the new name doesn't
+            ok(),
+            MockResponse::json(serde_json::json!({"ok": false, "error": "too_many_emoji"})),
+            ok(),
+            ok(),
+        ]);
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        let summary = rename(&client, rename_options(&["parrot", "parrot2"])).unwrap();
+
+        assert_eq!(summary, Summary::failed(1));
+        assert_eq!(images.finish(), vec!["GET /parrot.gif"]);
+        assert_eq!(
+            server.finish(),
+            vec![
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.add",
+                "POST /api/emoji.adminList",
+                "POST /api/emoji.remove",
+                "POST /api/emoji.add",
+                // party is added back for parrot
+                "POST /api/emoji.add",
+                "POST /api/emoji.remove",
+            ]
+        );
+    }
+}
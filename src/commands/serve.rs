@@ -0,0 +1,333 @@
+//! `serve` and `gallery`, browsing a download directory as HTML
+
+use crate::*;
+use std::fs::read;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Escapes text for use in HTML content and attribute values
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The MIME type of an image based on its file extension
+fn image_content_type(path: &std::path::Path) -> &'static str {
+    match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The images `serve` hands out by emoji name, the ones the index page showed
+type ServedImages = std::collections::HashMap<String, PathBuf>;
+
+/// Renders the gallery index page, only listing emoji whose name contains `query`
+///
+/// The images it shows are added to `images`, in whichever layout they were downloaded.
+fn render_index(
+    base_path: &std::path::Path,
+    query: &str,
+    images: &mut ServedImages,
+) -> std::io::Result<String> {
+    let mut emoji: Vec<Emoji> = read_emoji_dir(base_path)?
+        .filter(|e| e.name.contains(query))
+        .collect();
+    emoji.sort_by(|a, b| a.name.cmp(&b.name));
+    let names = read_names_file(base_path);
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Emoji</title>\
+         <style>figure{{display:inline-block;width:160px;text-align:center;vertical-align:top}}\
+         img{{max-width:64px;max-height:64px}}figcaption{{font-size:small}}</style></head><body>\n\
+         <form><input name=\"q\" value=\"{}\" placeholder=\"Search\" autofocus> \
+         <button>Search</button> {} emoji</form>\n",
+        html_escape(query),
+        emoji.len()
+    );
+    for e in &emoji {
+        let img = match find_image(base_path, e, &names) {
+            Some(image) => {
+                images.insert(e.name.clone(), image);
+                let mut url = reqwest::Url::parse("http://localhost/image").expect("valid URL");
+                url.query_pairs_mut().append_pair("name", &e.name);
+                format!(
+                    "<img src=\"/image?{}\" alt=\"{}\">",
+                    html_escape(url.query().unwrap_or_default()),
+                    html_escape(&e.name)
+                )
+            }
+            None if e.is_alias != 0 => format!("→ {}", html_escape(&e.alias_for)),
+            None => "(missing)".to_string(),
+        };
+        html += &format!(
+            "<figure>{}<figcaption><b>:{}:</b><br>{}<br>{}</figcaption></figure>\n",
+            img,
+            html_escape(&e.name),
+            html_escape(&e.user_display_name),
+            &e.created.to_string()[..10]
+        );
+    }
+    html += "</body></html>\n";
+    Ok(html)
+}
+
+pub(crate) fn serve(serve_opts: ServeOptions) -> Result<Summary, Error> {
+    let base_path = &serve_opts.path;
+
+    let address = format!("{}:{}", serve_opts.bind, serve_opts.port);
+    let server = tiny_http::Server::http(&address)
+        .map_err(|e| Error::InvalidArguments(format!("Could not listen on {}: {}", address, e)))?;
+    log::info!("Serving {:?} on http://{}/", base_path, address);
+
+    let mut images = ServedImages::new();
+    for request in server.incoming_requests() {
+        log::debug!("{} {}", request.method(), request.url());
+        let url = match reqwest::Url::parse(&format!("http://localhost{}", request.url())) {
+            Ok(url) => url,
+            Err(_) => {
+                request.respond(tiny_http::Response::empty(400)).ok();
+                continue;
+            }
+        };
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+        };
+
+        let result = match url.path() {
+            "/" => match render_index(base_path, &param("q").unwrap_or_default(), &mut images) {
+                Ok(html) => request.respond(
+                    tiny_http::Response::from_string(html).with_header(
+                        tiny_http::Header::from_bytes("Content-Type", "text/html; charset=utf-8")
+                            .expect("valid header"),
+                    ),
+                ),
+                Err(e) => {
+                    log::error!("Could not render index: {}", e);
+                    request.respond(tiny_http::Response::empty(500))
+                }
+            },
+            "/image" => {
+                // only images the index page showed, never paths from the request
+                let image = param("name").and_then(|name| images.get(&name));
+                match image.map(read) {
+                    Some(Ok(bytes)) => {
+                        let content_type = match image_format(&bytes) {
+                            Some("jpg") => "image/jpeg".to_string(),
+                            Some(format) => format!("image/{}", format),
+                            None => "application/octet-stream".to_string(),
+                        };
+                        request.respond(
+                            tiny_http::Response::from_data(bytes).with_header(
+                                tiny_http::Header::from_bytes("Content-Type", content_type)
+                                    .expect("valid header"),
+                            ),
+                        )
+                    }
+                    _ => request.respond(tiny_http::Response::empty(404)),
+                }
+            }
+            _ => request.respond(tiny_http::Response::empty(404)),
+        };
+        if let Err(e) = result {
+            log::error!("Could not respond: {}", e);
+        }
+    }
+    Ok(Summary::done())
+}
+
+/// Renders a single HTML page with all emoji grouped by year of creation
+///
+/// Images are embedded as data URIs unless `link_remote` is set.
+fn render_gallery(emoji: &[Emoji], base_path: &std::path::Path, link_remote: bool) -> String {
+    let mut by_year: std::collections::BTreeMap<i64, Vec<&Emoji>> = Default::default();
+    for e in emoji {
+        by_year.entry(e.created.date().0).or_default().push(e);
+    }
+
+    let mut missing = vec![];
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Emoji</title>\
+         <style>figure{display:inline-block;width:120px;text-align:center;vertical-align:top}\
+         img{max-width:64px;max-height:64px}figcaption{font-size:small}\
+         .alias{background:#eee;border-radius:4px;padding:0 4px}</style></head><body>\n",
+    );
+    for (year, emoji) in by_year.iter().rev() {
+        html += &format!("<h2>{}</h2>\n", year);
+        for e in emoji {
+            let content = if e.is_alias != 0 {
+                format!(
+                    "<span class=\"alias\">→ {}</span>",
+                    html_escape(&e.alias_for)
+                )
+            } else if link_remote {
+                format!("<img src=\"{}\" alt=\"\">", html_escape(&e.url))
+            } else {
+                let path = image_path(base_path, e);
+                match read(&path) {
+                    Ok(bytes) => format!(
+                        "<img src=\"data:{};base64,{}\" alt=\"\">",
+                        image_content_type(&path),
+                        base64::encode(&bytes)
+                    ),
+                    Err(_) => {
+                        missing.push(e.name.as_str());
+                        continue;
+                    }
+                }
+            };
+            html += &format!(
+                "<figure>{}<figcaption>:{}:</figcaption></figure>\n",
+                content,
+                html_escape(&e.name)
+            );
+        }
+    }
+    if !missing.is_empty() {
+        html += &format!("<h2>Missing ({})</h2>\n<ul>\n", missing.len());
+        for name in missing {
+            html += &format!("<li>:{}:</li>\n", html_escape(name));
+        }
+        html += "</ul>\n";
+    }
+    html += "</body></html>\n";
+    html
+}
+
+pub(crate) fn gallery(gallery_opts: GalleryOptions) -> Result<Summary, Error> {
+    let base_path = &gallery_opts.path;
+
+    let mut emoji: Vec<Emoji> = read_emoji_dir(base_path)
+        .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+        .collect();
+    emoji.sort_by(|a, b| a.created.cmp(&b.created).then(a.name.cmp(&b.name)));
+    log::debug!("Rendering {} emoji", emoji.len());
+
+    let html = render_gallery(&emoji, base_path, gallery_opts.link_remote);
+    let written = if gallery_opts.output.as_os_str() == "-" {
+        std::io::stdout().write_all(html.as_bytes())
+    } else {
+        std::fs::write(&gallery_opts.output, html)
+    };
+    match written {
+        Ok(()) => Ok(Summary::done()),
+        Err(e) => Err(Error::Failed(format!(
+            "Could not write {:?}: {}",
+            gallery_opts.output, e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod serve_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            html_escape("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn index() {
+        let dir = TestDir::new("serve-test-dir");
+        let mut ford = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false);
+        for name in &["parrot", "blob<wave>"] {
+            ford.write(name, serde_json::to_string(&Emoji::new(name)).unwrap())
+                .unwrap();
+        }
+        std::fs::write(dir.path.join("parrot.png"), b"png").unwrap();
+
+        let mut images = ServedImages::new();
+        let html = render_index(dir.path, "", &mut images).expect("could not render");
+        assert!(html.contains("<img src=\"/image?name=parrot\""));
+        assert!(html.contains(":blob&lt;wave&gt;:"));
+        assert!(html.contains("(missing)"));
+        assert_eq!(images.len(), 1);
+        assert_eq!(images["parrot"], dir.path.join("parrot.png"));
+
+        let html = render_index(dir.path, "parr", &mut images).expect("could not render");
+        assert!(!html.contains("blob"));
+    }
+
+    #[test]
+    fn other_layouts() {
+        let dir = TestDir::new("serve-layouts-test-dir");
+        let mut ford = FileOrDirectoryWriter::Directory(dir.path.to_path_buf(), false);
+        let mut by_user = Emoji::new("parrot");
+        by_user.user_display_name = "alice".into();
+        by_user.url = "https://x/parrot.gif".into();
+        let mut stored = Emoji::new("blob");
+        stored.image_sha256 = Some(sha256_hex(b"GIF89a"));
+        for e in &[&by_user, &stored] {
+            ford.write(&e.name, serde_json::to_string(e).unwrap())
+                .unwrap();
+        }
+        let user_image = Layout::ByUser.image_path(dir.path, &by_user);
+        std::fs::create_dir_all(user_image.parent().unwrap()).unwrap();
+        std::fs::write(&user_image, b"GIF89a").unwrap();
+        let object = object_path(dir.path, &sha256_hex(b"GIF89a"));
+        std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+        std::fs::write(&object, b"GIF89a").unwrap();
+
+        let mut images = ServedImages::new();
+        let html = render_index(dir.path, "", &mut images).expect("could not render");
+        assert!(!html.contains("(missing)"));
+        assert_eq!(images["parrot"], user_image);
+        assert_eq!(images["blob"], object);
+    }
+}
+
+#[cfg(test)]
+mod gallery_tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let mut alias = Emoji::new("alias");
+        alias.is_alias = 1;
+        alias.alias_for = "missing".into();
+        alias.created = Timestamp(1717243200);
+        let emoji = vec![Emoji::new("missing"), alias];
+
+        let html = render_gallery(
+            &emoji,
+            std::path::Path::new("gallery-test-nonexistent"),
+            false,
+        );
+        let (y2024, y1974) = (html.find("<h2>2024</h2>"), html.find("<h2>1974</h2>"));
+        assert!(y2024.is_some() && y1974.is_some() && y2024 < y1974);
+        assert!(html.contains("<span class=\"alias\">→ missing</span>"));
+        assert!(html.contains("<h2>Missing (1)</h2>\n<ul>\n<li>:missing:</li>"));
+
+        let html = render_gallery(
+            &emoji,
+            std::path::Path::new("gallery-test-nonexistent"),
+            true,
+        );
+        assert!(html.contains("<img src=\"https://cdn.example.com/emoji.png\""));
+        assert!(!html.contains("Missing"));
+    }
+}
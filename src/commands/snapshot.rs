@@ -0,0 +1,53 @@
+//! `snapshot`
+
+use crate::*;
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// The file name of a snapshot taken at some seconds since the epoch
+pub(crate) fn snapshot_file_name(seconds: u128) -> String {
+    let (year, month, day) = civil_date(seconds);
+    let time = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.json",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+pub(crate) fn snapshot(
+    client: &RetryingClient,
+    snapshot_opts: SnapshotOptions,
+) -> Result<Summary, Error> {
+    let slack = snapshot_opts.slack;
+
+    let emoji = get_emoji(client, &slack.workspace, &slack.token)
+        .map_err(|e| Error::Api("Could not get emojis".into(), e))?;
+    let serialized = serde_json::to_string_pretty(&emoji)
+        .map_err(|e| Error::Serialization("Could not serialize".into(), e))?;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as u128)
+        .unwrap_or_default();
+    let path = snapshot_opts.path.join(snapshot_file_name(now));
+    log::debug!("Writing {} emoji to {:?}", emoji.len(), path);
+
+    let written = std::fs::create_dir_all(&snapshot_opts.path)
+        .and_then(|_| OpenOptions::new().write(true).create_new(true).open(&path))
+        .and_then(|mut file| file.write_all((serialized + "\n").as_bytes()));
+    match written {
+        Ok(()) => {
+            println!("{}", path.display());
+            Ok(Summary::done())
+        }
+        Err(e) => Err(Error::Failed(format!(
+            "Could not write snapshot {:?}: {}",
+            path, e
+        ))),
+    }
+}
@@ -0,0 +1,282 @@
+//! `sprite`, one image and a stylesheet for many emoji
+
+use crate::*;
+use std::fs::read;
+
+pub(crate) fn sprite(
+    sprite_opts: SpriteOptions,
+    global_opts: GlobalOptions,
+) -> Result<Summary, Error> {
+    let global_opts = sprite_opts.global + global_opts;
+    require_resize(true)?;
+    let base_path = &sprite_opts.path;
+    let (output, css_path) = (&sprite_opts.output, &sprite_opts.css);
+    let cell = sprite_opts.cell_size;
+
+    let mut emoji: Vec<Emoji> = read_emoji_dir(base_path)
+        .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+        .collect();
+    emoji.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut aliases: std::collections::HashMap<&str, Vec<&str>> = std::collections::HashMap::new();
+    for e in emoji.iter().filter(|e| e.is_alias != 0) {
+        aliases.entry(&e.alias_for).or_default().push(&e.name);
+    }
+
+    let names = read_names_file(base_path);
+    let (mut images, mut animated, mut failed) = (vec![], vec![], 0);
+    for e in emoji.iter().filter(|e| e.is_alias == 0) {
+        let path = match find_image(base_path, e, &names) {
+            Some(path) => path,
+            None => {
+                if global_opts.verbose {
+                    log::info!("{}: No image found, leaving it out", e.name);
+                }
+                continue;
+            }
+        };
+        match read(&path) {
+            Ok(bytes) if image_animated(&bytes) == Some(true) => animated.push((e, path)),
+            Ok(_) => images.push((e, path)),
+            Err(error) => {
+                log::error!("{}: Could not read {:?}: {}", e.name, path, error);
+                failed += 1;
+            }
+        }
+    }
+    if images.is_empty() {
+        return Err(Error::Failed(format!(
+            "No static images in {:?} to put into a sprite sheet",
+            base_path
+        )));
+    }
+
+    let columns = sprite_columns(images.len());
+    let paths: Vec<&std::path::Path> = images.iter().map(|(_, path)| path.as_path()).collect();
+    render_sprite(&paths, columns, cell, output).map_err(Error::Failed)?;
+
+    let image_url = sprite_opts.image_url.unwrap_or_else(|| {
+        output
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    let names: Vec<&str> = images.iter().map(|(e, _)| e.name.as_str()).collect();
+    let css = sprite_css(&names, &aliases, columns, cell, &image_url);
+    write_atomically(css_path, css.as_bytes())
+        .map_err(|e| Error::Io(format!("Could not write {:?}", css_path), e))?;
+
+    let animated_path = sprite_opts
+        .animated
+        .unwrap_or_else(|| output.with_extension("json"));
+    let animated_json = serde_json::Value::Array(
+        animated
+            .iter()
+            .map(|(e, path)| {
+                serde_json::json!({
+                    "name": e.name,
+                    "path": path.strip_prefix(base_path).unwrap_or(path),
+                    "aliases": aliases.get(e.name.as_str()).cloned().unwrap_or_default(),
+                })
+            })
+            .collect(),
+    );
+    let serialized = serde_json::to_string_pretty(&animated_json)
+        .map_err(|e| Error::Serialization("Could not serialize".into(), e))?
+        + "\n";
+    write_atomically(&animated_path, serialized.as_bytes())
+        .map_err(|e| Error::Io(format!("Could not write {:?}", animated_path), e))?;
+
+    log::info!(
+        "Put {} emoji into {:?}, listed {} animated ones in {:?}",
+        images.len(),
+        output,
+        animated.len(),
+        animated_path
+    );
+    Ok(Summary::failed(failed))
+}
+
+/// How many columns a sprite sheet of `count` emoji has, to make it about square
+fn sprite_columns(count: usize) -> usize {
+    (1..)
+        .find(|columns| columns * columns >= count)
+        .unwrap_or(1)
+}
+
+/// Escapes an emoji name for a CSS class selector, like the `+` in `+1`
+fn css_escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii() && !c.is_ascii_alphanumeric() && c != '-' && c != '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A class per emoji that shows its cell of the sprite sheet, which its aliases share
+///
+/// `names` are in the order the emoji are in the sprite sheet, row by row.
+fn sprite_css(
+    names: &[&str],
+    aliases: &std::collections::HashMap<&str, Vec<&str>>,
+    columns: usize,
+    cell: CellSize,
+    image_url: &str,
+) -> String {
+    let mut css = format!(
+        ".emoji {{\n  display: inline-block;\n  width: {}px;\n  height: {}px;\n  \
+         background-image: url(\"{}\");\n  background-repeat: no-repeat;\n}}\n",
+        cell.width,
+        cell.height,
+        image_url.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    for (i, name) in names.iter().enumerate() {
+        let selectors: Vec<String> = std::iter::once(name)
+            .chain(aliases.get(name).into_iter().flatten())
+            .map(|name| format!(".emoji-{}", css_escape(name)))
+            .collect();
+        let x = (i % columns) as u32 * cell.width;
+        let y = (i / columns) as u32 * cell.height;
+        let position = |offset: u32| match offset {
+            0 => "0".to_string(),
+            offset => format!("-{}px", offset),
+        };
+        css += &format!(
+            "{} {{ background-position: {} {}; }}\n",
+            selectors.join(", "),
+            position(x),
+            position(y)
+        );
+    }
+    css
+}
+
+/// Scales each image to fit a cell and arranges them in rows of `columns` via ImageMagick
+///
+/// Each image is scaled by an ImageMagick run of its own, which returns its pixels, so any
+/// number of them fit. The sheet is put together here and encoded by a last run.
+#[cfg(feature = "resize")]
+fn render_sprite(
+    images: &[&std::path::Path],
+    columns: usize,
+    cell: CellSize,
+    output: &std::path::Path,
+) -> Result<(), String> {
+    let size = format!("{}x{}", cell.width, cell.height);
+    let (width, height) = (cell.width as usize, cell.height as usize);
+    let row_bytes = columns * width * 4;
+    let mut sheet = vec![0; row_bytes * height * images.len().div_ceil(columns)];
+    for (i, path) in images.iter().enumerate() {
+        let image = read(path).map_err(|e| format!("Could not read {:?}: {}", path, e))?;
+        // only the first frame, to be sure
+        let pixels = convert(
+            &image,
+            &[
+                "-[0]",
+                "-background",
+                "none",
+                "-resize",
+                &size,
+                "-gravity",
+                "center",
+                "-extent",
+                &size,
+                "-depth",
+                "8",
+                "rgba:-",
+            ],
+        )
+        .map_err(|e| format!("{:?}: {}", path, e))?;
+        if pixels.len() != width * height * 4 {
+            return Err(format!(
+                "{:?}: ImageMagick returned {} bytes",
+                path,
+                pixels.len()
+            ));
+        }
+        let (x, y) = (i % columns * width * 4, i / columns * height);
+        for (line, cell_line) in pixels.chunks(width * 4).enumerate() {
+            let start = (y + line) * row_bytes + x;
+            sheet[start..start + width * 4].copy_from_slice(cell_line);
+        }
+    }
+    let sheet_size = format!("{}x{}", columns * width, sheet.len() / row_bytes);
+    let png = convert(
+        &sheet,
+        &["-size", &sheet_size, "-depth", "8", "rgba:-", "PNG32:-"],
+    )?;
+    write_atomically(output, &png).map_err(|e| format!("Could not write {:?}: {}", output, e))
+}
+
+#[cfg(not(feature = "resize"))]
+fn render_sprite(
+    _images: &[&std::path::Path],
+    _columns: usize,
+    _cell: CellSize,
+    _output: &std::path::Path,
+) -> Result<(), String> {
+    Err(NO_RESIZE.to_string())
+}
+
+#[cfg(test)]
+mod sprite_tests {
+    use super::*;
+
+    #[test]
+    fn cell_sizes() {
+        let square: CellSize = "32".parse().unwrap();
+        assert_eq!((square.width, square.height), (32, 32));
+        let wide: CellSize = "48x32".parse().unwrap();
+        assert_eq!((wide.width, wide.height), (48, 32));
+        assert!("0".parse::<CellSize>().is_err());
+        assert!("32x".parse::<CellSize>().is_err());
+        assert!("big".parse::<CellSize>().is_err());
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(sprite_columns(1), 1);
+        assert_eq!(sprite_columns(4), 2);
+        assert_eq!(sprite_columns(5), 3);
+        assert_eq!(sprite_columns(1000), 32);
+    }
+
+    #[test]
+    fn css() {
+        assert_eq!(css_escape("party-parrot_2"), "party-parrot_2");
+        assert_eq!(css_escape("+1"), "\\+1");
+        assert_eq!(css_escape("a.b:c"), "a\\.b\\:c");
+
+        let mut aliases = std::collections::HashMap::new();
+        aliases.insert("b", vec!["bee", "+1"]);
+        let cell = CellSize {
+            width: 48,
+            height: 32,
+        };
+        let css = sprite_css(&["a", "b", "c"], &aliases, 2, cell, "img/sprite.png");
+        assert!(css
+            .starts_with(".emoji {\n  display: inline-block;\n  width: 48px;\n  height: 32px;\n"));
+        assert!(css.contains("  background-image: url(\"img/sprite.png\");\n"));
+        assert!(css.contains("\n.emoji-a { background-position: 0 0; }\n"));
+        assert!(
+            css.contains("\n.emoji-b, .emoji-bee, .emoji-\\+1 { background-position: -48px 0; }\n")
+        );
+        assert!(css.ends_with("\n.emoji-c { background-position: 0 -32px; }\n"));
+    }
+
+    #[cfg(not(feature = "resize"))]
+    #[test]
+    fn needs_resize_feature() {
+        use structopt::StructOpt;
+        let args = ["slack-emoji", "sprite", "sprite-test-missing-dir"];
+        let result = run(Cli::from_iter_safe(args).expect("valid arguments"));
+        assert!(
+            matches!(result, Err(Error::InvalidArguments(_))),
+            "{:?}",
+            result
+        );
+    }
+}
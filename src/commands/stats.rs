@@ -0,0 +1,156 @@
+//! `stats`
+
+use crate::*;
+
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct Contributor {
+    pub(crate) name: String,
+    count: usize,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct Stats {
+    total: usize,
+    emoji: usize,
+    aliases: usize,
+    top_contributors: Vec<Contributor>,
+    per_year: std::collections::BTreeMap<i64, usize>,
+    /// Emoji whose images were checked by `download` or `analyze`
+    pub(crate) analyzed: usize,
+    pub(crate) animated: usize,
+    /// Who added the most animated emoji
+    pub(crate) top_animators: Vec<Contributor>,
+}
+
+impl Stats {
+    pub fn new(emoji: &[Emoji]) -> Stats {
+        let aliases = emoji.iter().filter(|e| e.is_alias != 0).count();
+
+        let mut per_user = std::collections::HashMap::new();
+        let mut per_year = std::collections::BTreeMap::new();
+        let mut animated_per_user = std::collections::HashMap::new();
+        for e in emoji {
+            *per_user.entry(e.user_display_name.as_str()).or_insert(0) += 1;
+            *per_year.entry(e.created.date().0).or_insert(0) += 1;
+            if e.animated == Some(true) {
+                *animated_per_user
+                    .entry(e.user_display_name.as_str())
+                    .or_insert(0) += 1;
+            }
+        }
+
+        Stats {
+            total: emoji.len(),
+            emoji: emoji.len() - aliases,
+            aliases,
+            top_contributors: Stats::top(per_user),
+            per_year,
+            analyzed: emoji.iter().filter(|e| e.animated.is_some()).count(),
+            animated: animated_per_user.values().sum(),
+            top_animators: Stats::top(animated_per_user),
+        }
+    }
+
+    /// The ten users with the highest counts
+    fn top(per_user: std::collections::HashMap<&str, usize>) -> Vec<Contributor> {
+        let mut top: Vec<Contributor> = per_user
+            .into_iter()
+            .map(|(name, count)| Contributor {
+                name: name.to_string(),
+                count,
+            })
+            .collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+        top.truncate(10);
+        top
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} in total: {} emoji, {} aliases",
+            self.total, self.emoji, self.aliases
+        )?;
+
+        writeln!(f, "\nTop contributors:")?;
+        for (i, c) in self.top_contributors.iter().enumerate() {
+            writeln!(f, "{:>4}. {:<30} {}", i + 1, c.name, c.count)?;
+        }
+
+        writeln!(f, "\nPer year:")?;
+        let max = self.per_year.values().copied().max().unwrap_or(1);
+        for (year, count) in &self.per_year {
+            let bar = "#".repeat((count * 50).div_ceil(max));
+            writeln!(f, "{:>6} {:<50} {}", year, bar, count)?;
+        }
+
+        // only local directories know which images are animated
+        if self.analyzed > 0 {
+            writeln!(
+                f,
+                "\n{} of {} analyzed emoji are animated",
+                self.animated, self.analyzed
+            )?;
+            if !self.top_animators.is_empty() {
+                writeln!(f, "\nTop contributors of animated emoji:")?;
+                for (i, c) in self.top_animators.iter().enumerate() {
+                    writeln!(f, "{:>4}. {:<30} {}", i + 1, c.name, c.count)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn stats(client: &RetryingClient, stats_opts: StatsOptions) -> Result<Summary, Error> {
+    let emoji = stats_opts.source.load(client)?;
+    log::debug!("Loaded {} emoji", emoji.len());
+    let stats = Stats::new(&emoji);
+    if stats_opts.json {
+        print_json(&stats)?;
+    } else {
+        print!("{}", stats);
+    }
+    Ok(Summary::done())
+}
+
+/// Finds the names closest to a misspelled one
+pub(crate) fn similar_names<'a>(name: &str, names: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut candidates: Vec<(usize, &str)> = names
+        .map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 3)
+        .collect();
+    candidates.sort();
+    candidates.into_iter().take(5).map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(951782400), (2000, 2, 29));
+        assert_eq!(civil_date(1717243199), (2024, 6, 1));
+    }
+
+    #[test]
+    fn aggregates() {
+        let mut alias = Emoji::new("alias");
+        alias.is_alias = 1;
+        alias.user_display_name = "someone".into();
+        alias.created = Timestamp(1717243199);
+        let stats = Stats::new(&[Emoji::new("a"), Emoji::new("b"), alias]);
+
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.emoji, 2);
+        assert_eq!(stats.aliases, 1);
+        assert_eq!(stats.top_contributors[0].name, "M3t0r");
+        assert_eq!(stats.top_contributors[0].count, 2);
+        assert_eq!(stats.per_year.get(&1974), Some(&2));
+        assert_eq!(stats.per_year.get(&2024), Some(&1));
+    }
+}
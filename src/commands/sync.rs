@@ -0,0 +1,255 @@
+//! `sync push` and `sync pull`
+
+use crate::*;
+use std::path::PathBuf;
+
+pub(crate) fn sync_push(
+    client: &RetryingClient,
+    push_opts: SyncPushOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = push_opts.global + global_opts;
+    let slack = push_opts.slack;
+    require_resize(push_opts.resize.auto_resize)?;
+
+    let images = read_image_dir(&push_opts.path)
+        .map_err(|e| Error::Io("could not read images from directory".into(), e))?;
+    // metadata written by `list` knows the original name and whether it's just an alias
+    let metadata: std::collections::HashMap<String, Emoji> = read_emoji_dir(&push_opts.path)
+        .map(|emoji| emoji.map(|e| (normalize_emoji_name(&e.name), e)).collect())
+        .unwrap_or_default();
+
+    let existing = get_emoji_names(client, &slack)?;
+
+    let mut to_upload = vec![];
+    let mut present = 0;
+    for (name, path) in images {
+        let name = metadata
+            .get(&name)
+            .map(|e| normalize_emoji_name(&e.name))
+            .unwrap_or(name);
+        if metadata.get(&name).is_some_and(|e| e.is_alias != 0) {
+            log::debug!("{}: is an alias, skipping", name);
+        } else if existing.contains(&name) {
+            log::debug!("{}: already present", name);
+            present += 1;
+        } else {
+            to_upload.push((name, path));
+        }
+    }
+
+    log::info!(
+        "{} to upload, {} already present in {}",
+        to_upload.len(),
+        present,
+        slack.workspace
+    );
+    if global_opts.verbose || push_opts.dry_run {
+        for (name, path) in &to_upload {
+            log::info!("  {} <- {:?}", name, path);
+        }
+    }
+    if push_opts.dry_run || to_upload.is_empty() {
+        return Ok(Summary::done());
+    }
+
+    let (uploaded, failed) = upload_images(
+        client,
+        &slack,
+        &to_upload,
+        push_opts.resize.auto_resize,
+        &global_opts,
+        progress,
+    );
+    log::info!("{} uploaded, {} failed", uploaded, failed);
+    Ok(Summary::failed(failed))
+}
+
+pub(crate) fn sync_pull(
+    client: &RetryingClient,
+    pull_opts: SyncPullOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = pull_opts.global + global_opts;
+    let slack = pull_opts.slack;
+    let base_path = pull_opts.path;
+
+    // read before writing the new metadata so we know which emoji were there before
+    let local: Vec<Emoji> = if base_path.is_dir() {
+        read_emoji_dir(&base_path)
+            .map_err(|e| Error::Io("could not read json files from directory".into(), e))?
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let mut emoji = get_emoji(client, &slack.workspace, &slack.token)
+        .map_err(|e| Error::Api("Could not get emojis".into(), e))?;
+    assign_file_names(&mut emoji);
+
+    let mut ford_writer = FileOrDirectoryWriter::Directory(base_path.clone(), false);
+    let pb = progress.start(emoji.len() as u64, PROGRESS_TEMPLATE);
+    let mut failed = write_emoji_metadata(
+        &mut ford_writer,
+        &emoji,
+        &global_opts,
+        OutputFormat::Text,
+        &pb,
+    );
+    pb.finish_with_message(&format!("{} emoji written", emoji.len()));
+
+    let emoji_path_pairs: Vec<(&Emoji, PathBuf)> = emoji
+        .iter()
+        .filter(|e| e.is_alias == 0)
+        .map(|e| (e, image_path(&base_path, e)))
+        .collect();
+    let pb = progress.start(emoji_path_pairs.len() as u64, PROGRESS_TEMPLATE);
+    failed += download_images(
+        client,
+        &emoji_path_pairs,
+        &DownloadSettings::default(),
+        &global_opts,
+        &pb,
+    )
+    .failed;
+    pb.finish_with_message("Images downloaded");
+
+    let remote: std::collections::HashSet<&str> = emoji.iter().map(|e| e.name.as_str()).collect();
+    let stale: Vec<&Emoji> = local
+        .iter()
+        .filter(|e| !remote.contains(e.name.as_str()))
+        .collect();
+    if !stale.is_empty() && !pull_opts.prune {
+        log::info!(
+            "{} local emoji no longer exist in {}, use --prune to remove them",
+            stale.len(),
+            slack.workspace
+        );
+    } else if !stale.is_empty() {
+        failed += remove_local_emoji(&base_path, &stale, false);
+    }
+    Ok(Summary::failed(failed))
+}
+
+#[cfg(test)]
+mod sync_pull_tests {
+    use super::*;
+    use reqwest::blocking::Client;
+    use slack_emoji::test_support::{MockResponse, MockServer, TestDir};
+    use structopt::StructOpt;
+
+    fn gif() -> MockResponse {
+        MockResponse {
+            status: 200,
+            headers: vec![("Content-Type", "image/gif".into())],
+            body: b"GIF89a".to_vec(),
+        }
+    }
+
+    fn admin_list(images: &MockServer) -> MockResponse {
+        MockResponse::json(serde_json::json!({
+            "ok": true,
+            "custom_emoji_total_count": 2,
+            "paging": {"count": 1000, "page": 1, "pages": 1},
+            "emoji": [
+                {"name": "parrot", "url": format!("{}/parrot.gif", images.url), "created": 1},
+                {
+                    "name": "party",
+                    "url": "alias:parrot",
+                    "is_alias": 1,
+                    "alias_for": "parrot",
+                    "created": 2
+                },
+            ],
+        }))
+    }
+
+    fn pull(server: &MockServer, extra_args: &[&str]) -> Result<Summary, Error> {
+        let args = [
+            "slack-emoji",
+            "sync",
+            "pull",
+            "--workspace",
+            "acme",
+            "--token",
+            "xoxs-1",
+        ];
+        let pull_opts = match Cli::from_iter_safe(args.iter().chain(extra_args))
+            .unwrap()
+            .command
+        {
+            Commands::Sync(SyncCommands::Pull(pull_opts)) => pull_opts,
+            command => panic!("not a sync pull command: {:?}", command),
+        };
+        let client = RetryingClient::new(Client::new(), 0).with_base_url(&server.url);
+        sync_pull(
+            &client,
+            pull_opts,
+            GlobalOptions::default(),
+            ProgressMode::None,
+        )
+    }
+
+    fn write_stale(dir: &std::path::Path) {
+        std::fs::write(
+            dir.join("old.json"),
+            serde_json::to_string(&Emoji::new("old")).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.join("old.png"), b"test").unwrap();
+    }
+
+    #[test]
+    fn mirrors_the_workspace() {
+        let dir = TestDir::new("sync-pull-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        write_stale(dir.path);
+        let images = MockServer::start(vec![gif()]);
+        let server = MockServer::start(vec![admin_list(&images)]);
+
+        let summary = pull(&server, &["--prune", dir.path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(summary.failed, 0);
+        assert_eq!(server.finish(), vec!["POST /api/emoji.adminList"]);
+        assert_eq!(images.finish(), vec!["GET /parrot.gif"]);
+        assert_eq!(
+            std::fs::read(dir.path.join("parrot.gif")).unwrap(),
+            b"GIF89a"
+        );
+        assert!(dir.path.join("parrot.json").is_file());
+        assert!(dir.path.join("party.json").is_file());
+        assert!(!dir.path.join("old.json").exists());
+        assert!(!dir.path.join("old.png").exists());
+    }
+
+    #[test]
+    fn keeps_stale_emoji_without_prune() {
+        let dir = TestDir::new("sync-pull-failure-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        write_stale(dir.path);
+        let images = MockServer::start(vec![MockResponse::status(404)]);
+        let server = MockServer::start(vec![admin_list(&images)]);
+
+        let summary = pull(&server, &[dir.path.to_str().unwrap()]).unwrap();
+
+        // the image that could not be downloaded
+        assert_eq!(summary.failed, 1);
+        assert_eq!(images.finish(), vec!["GET /parrot.gif"]);
+        assert!(!dir.path.join("parrot.gif").exists());
+        assert!(dir.path.join("parrot.json").is_file());
+        assert!(dir.path.join("old.json").is_file());
+        assert!(dir.path.join("old.png").is_file());
+
+        // without a workspace to compare to, nothing changes
+        let server = MockServer::start(vec![MockResponse::json(
+            serde_json::json!({"ok": false, "error": "invalid_auth"}),
+        )]);
+        assert!(matches!(
+            pull(&server, &["--prune", dir.path.to_str().unwrap()]),
+            Err(Error::Api(..))
+        ));
+        assert!(dir.path.join("old.json").is_file());
+    }
+}
@@ -0,0 +1,117 @@
+//! `top`
+
+use crate::*;
+
+/// Counts emoji per group and ranks the groups, most emoji first
+fn rank<'a>(
+    emoji: impl Iterator<Item = &'a Emoji>,
+    by: TopBy,
+    limit: usize,
+) -> Vec<(String, usize)> {
+    let mut counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for e in emoji {
+        let key = match by {
+            TopBy::User => e.user_display_name.clone(),
+            TopBy::Month => {
+                let (year, month, _) = e.created.date();
+                format!("{:04}-{:02}", year, month)
+            }
+        };
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+pub(crate) fn top(client: &RetryingClient, top_opts: TopOptions) -> Result<Summary, Error> {
+    let emoji = top_opts.source.load(client)?;
+    let since = top_opts.since.unwrap_or(0);
+    // until is inclusive, so count the whole day
+    let until = top_opts.until.map_or(u128::MAX, |until| until + 86400);
+    let selected: Vec<&Emoji> = emoji
+        .iter()
+        .filter(|e| since <= e.created.seconds() && e.created.seconds() < until)
+        .collect();
+    log::debug!("{} of {} emoji in range", selected.len(), emoji.len());
+
+    let ranked = rank(selected.into_iter(), top_opts.by, top_opts.limit);
+    for (i, (key, count)) in ranked.iter().enumerate() {
+        println!("{:>4}. {:<30} {}", i + 1, key, count);
+    }
+    Ok(Summary::done())
+}
+
+#[cfg(test)]
+mod top_tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("1970-01-01"), Ok(0));
+        assert_eq!(parse_date("2000-02-29"), Ok(951782400));
+        assert_eq!(parse_date("2024-06-01"), Ok(1717200000));
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-02-31").is_err());
+        assert!(parse_date("2024-04-31").is_err());
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2100-02-29").is_err());
+        assert_eq!(parse_date("2024-02-29"), Ok(1709164800));
+        assert!(parse_date("2024-06-00").is_err());
+        assert!(parse_date("yesterday").is_err());
+
+        let now = Timestamp(1717243199); // 2024-06-01T11:59:59Z
+        assert_eq!(
+            parse_time("2024-06-01", now, false),
+            Ok(Timestamp(1717200000))
+        );
+        assert_eq!(
+            parse_time("2024-06-01", now, true),
+            Ok(Timestamp(1717286399))
+        );
+        assert_eq!(
+            parse_time("2024-06-01T12:30:00Z", now, true),
+            Ok(Timestamp(1717245000))
+        );
+        assert_eq!(
+            parse_time("2024-06-01 12:30", now, false),
+            Ok(Timestamp(1717245000))
+        );
+        assert_eq!(
+            parse_time("30 days ago", now, false),
+            Ok(Timestamp(1717243199 - 30 * 86400))
+        );
+        assert_eq!(
+            parse_time("1 Week ago", now, false),
+            Ok(Timestamp(1717243199 - 7 * 86400))
+        );
+        assert_eq!(parse_time("today", now, false), Ok(Timestamp(1717200000)));
+        assert_eq!(
+            parse_time("yesterday", now, true),
+            Ok(Timestamp(1717199999))
+        );
+        assert!(parse_time("30 fortnights ago", now, false).is_err());
+        assert!(parse_time("ago", now, false).is_err());
+        let huge = format!("{} years ago", u128::MAX / 1000);
+        assert!(parse_time(&huge, now, false).is_err());
+        assert_eq!(parse_time("100000 years ago", now, false), Ok(Timestamp(0)));
+        assert!(parse_time("2024-06-01T25:00:00Z", now, false).is_err());
+    }
+
+    #[test]
+    fn ranking() {
+        let mut emoji = [Emoji::new("a"), Emoji::new("b"), Emoji::new("c")];
+        emoji[2].user_display_name = "someone".into();
+        emoji[2].created = Timestamp(1717243200);
+
+        assert_eq!(
+            rank(emoji.iter(), TopBy::User, 10),
+            vec![("M3t0r".to_string(), 2), ("someone".to_string(), 1)]
+        );
+        assert_eq!(
+            rank(emoji.iter(), TopBy::Month, 1),
+            vec![("1974-03".to_string(), 2)]
+        );
+    }
+}
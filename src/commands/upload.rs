@@ -0,0 +1,218 @@
+//! `upload`
+
+use crate::*;
+use std::fs::read;
+use std::path::PathBuf;
+
+pub(crate) fn get_emoji_names(
+    client: &RetryingClient,
+    slack: &WorkspaceOptions,
+) -> Result<std::collections::HashSet<String>, Error> {
+    match get_emoji(client, &slack.workspace, &slack.token) {
+        Ok(emoji) => Ok(emoji
+            .into_iter()
+            .map(|e| normalize_emoji_name(&e.name))
+            .collect()),
+        Err(e) => Err(Error::Api("Could not get emojis".into(), e)),
+    }
+}
+
+pub(crate) fn upload(
+    client: &RetryingClient,
+    upload_opts: UploadOptions,
+    global_opts: GlobalOptions,
+    progress: ProgressMode,
+) -> Result<Summary, Error> {
+    let global_opts = upload_opts.global + global_opts;
+    let slack = upload_opts.slack;
+    require_resize(upload_opts.resize.auto_resize)?;
+    if upload_opts.check_auth {
+        require_auth(client, &slack)?;
+    }
+
+    let images = read_image_dir(&upload_opts.path)
+        .map_err(|e| Error::Io("could not read images from directory".into(), e))?;
+
+    let existing = get_emoji_names(client, &slack)?;
+    let (images, present): (Vec<_>, Vec<_>) = images
+        .into_iter()
+        .partition(|(name, _)| !existing.contains(name));
+    for (name, _) in &present {
+        log::debug!("{}: already exists, skipping", name);
+    }
+
+    let (uploaded, failed) = upload_images(
+        client,
+        &slack,
+        &images,
+        upload_opts.resize.auto_resize,
+        &global_opts,
+        progress,
+    );
+    log::info!(
+        "{} uploaded, {} skipped, {} failed",
+        uploaded,
+        present.len(),
+        failed
+    );
+    Ok(Summary::failed(failed))
+}
+
+/// Uploads `(emoji name, path)` pairs, returns the number of uploaded and failed emoji
+pub(crate) fn upload_images(
+    client: &RetryingClient,
+    slack: &WorkspaceOptions,
+    images: &[(String, PathBuf)],
+    auto_resize: bool,
+    global_opts: &GlobalOptions,
+    progress: ProgressMode,
+) -> (usize, usize) {
+    let pb = progress.start(images.len() as u64, PROGRESS_TEMPLATE);
+
+    let rate_limiter = RateLimiter::new(20.0); // 20 uploads / s
+    let (mut uploaded, mut failed) = (0, 0);
+
+    for (name, path) in track(&pb, images.iter()) {
+        pb.set_message(name);
+        if global_opts.verbose {
+            pb.println(&format!("Uploading {:?} as {}", path, name));
+        }
+
+        let image = match read(path) {
+            Ok(image) => image,
+            Err(e) => {
+                pb.println(&format!("{}: Could not read {:?}: {}", name, path, e));
+                failed += 1;
+                continue;
+            }
+        };
+        let image = match fit_upload_limits(name, image, auto_resize, &*pb) {
+            Ok(image) => image,
+            Err(error) => {
+                pb.println(&format!("{}: {}", name, error));
+                failed += 1;
+                continue;
+            }
+        };
+
+        match add_emoji(client, &slack.workspace, &slack.token, name, image) {
+            Ok(()) => uploaded += 1,
+            Err(e) => {
+                pb.println(&format!("{}: Could not upload: {}", name, e));
+                failed += 1;
+            }
+        }
+
+        rate_limiter.wait();
+    }
+
+    pb.finish_with_message("All done");
+    (uploaded, failed)
+}
+
+#[cfg(test)]
+mod upload_tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn image_names() {
+        assert_eq!(
+            emoji_name_from_image_path(Path::new("dir/partyparrot.gif")),
+            Some("partyparrot".to_string())
+        );
+        assert_eq!(
+            emoji_name_from_image_path(Path::new("blob-wave.JPEG")),
+            Some("blob-wave".to_string())
+        );
+        assert_eq!(
+            emoji_name_from_image_path(Path::new("My_Emoji.PNG")),
+            Some("my_emoji".to_string())
+        );
+        assert_eq!(emoji_name_from_image_path(Path::new("blob.json")), None);
+        assert_eq!(emoji_name_from_image_path(Path::new("README")), None);
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn dimensions() {
+        assert_eq!(image_dimensions(&png(300, 128)), Some((300, 128)));
+        assert_eq!(image_dimensions(b"GIF89a\x2c\x01\x80\0"), Some((300, 128)));
+        let jpg = [
+            &[0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0][..],
+            &[0xff, 0xc4, 0, 2],
+            &[0xff, 0xc2, 0, 11, 8, 0, 0x80, 0x01, 0x2c],
+        ]
+        .concat();
+        assert_eq!(image_dimensions(&jpg), Some((300, 128)));
+        assert_eq!(image_dimensions(&jpg[..12]), None);
+
+        let webp = |chunk: &[u8], header: &[u8]| [b"RIFF\0\0\0\0WEBP", chunk, header].concat();
+        let lossy = webp(b"VP8 ", b"\0\0\0\0\0\0\0\0\0\0\x2c\x01\x80\0");
+        assert_eq!(image_dimensions(&lossy), Some((300, 128)));
+        let lossless = webp(b"VP8L", b"\0\0\0\0\x2f\x2b\xc1\x1f\0");
+        assert_eq!(image_dimensions(&lossless), Some((300, 128)));
+        let extended = webp(b"VP8X", b"\0\0\0\0\0\0\0\0\x2b\x01\0\x7f\0\0");
+        assert_eq!(image_dimensions(&extended), Some((300, 128)));
+
+        assert_eq!(image_dimensions(b"<html>"), None);
+    }
+
+    #[test]
+    fn limits() {
+        let pb = indicatif::ProgressBar::hidden();
+        assert_eq!(upload_limit_problem(&png(128, 128)), None);
+        assert_eq!(
+            fit_upload_limits("small", png(64, 32), false, &pb),
+            Ok(png(64, 32))
+        );
+
+        assert_eq!(
+            upload_limit_problem(&png(300, 128)).unwrap(),
+            "Image is 300×128 pixels, larger than Slack's 128×128"
+        );
+        let mut big = png(128, 64);
+        big.resize(MAX_EMOJI_BYTES as usize + 1, 0);
+        assert_eq!(
+            upload_limit_problem(&big).unwrap(),
+            "Image is 131073 bytes, more than Slack's 131072"
+        );
+        big[16..24].copy_from_slice(&png(129, 64)[16..]);
+        let error = fit_upload_limits("big", big, false, &pb).unwrap_err();
+        assert_eq!(
+            error,
+            "Image is 129×64 pixels, larger than Slack's 128×128 and 131073 bytes, \
+             more than Slack's 131072, skipping it (see --auto-resize)"
+        );
+    }
+
+    #[cfg(feature = "resize")]
+    #[test]
+    fn finds_imagemagick() {
+        match convert(b"", &["-version"]) {
+            Ok(version) => assert!(String::from_utf8_lossy(&version).contains("ImageMagick")),
+            Err(e) => assert!(e.starts_with("ImageMagick isn't installed"), "{}", e),
+        }
+    }
+
+    #[cfg(not(feature = "resize"))]
+    #[test]
+    fn no_resize() {
+        assert!(require_resize(false).is_ok());
+        assert!(matches!(
+            require_resize(true),
+            Err(Error::InvalidArguments(_))
+        ));
+        let pb = indicatif::ProgressBar::hidden();
+        assert_eq!(
+            fit_upload_limits("big", png(256, 256), true, &pb).unwrap_err(),
+            NO_RESIZE
+        );
+    }
+}
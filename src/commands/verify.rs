@@ -0,0 +1,176 @@
+//! `verify`
+
+use crate::*;
+use std::fs::{read, read_dir, remove_file};
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub(crate) struct VerifyReport {
+    /// Names of non-alias emoji with metadata but without an image
+    pub(crate) missing_images: Vec<String>,
+    /// Images without metadata
+    pub(crate) orphan_images: Vec<PathBuf>,
+    empty_files: Vec<PathBuf>,
+    /// Images whose content doesn't match their extension
+    mismatched: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// How many problems were found
+    pub fn len(&self) -> usize {
+        self.missing_images.len()
+            + self.orphan_images.len()
+            + self.empty_files.len()
+            + self.mismatched.len()
+    }
+}
+
+pub(crate) fn verify_dir(path: &std::path::Path) -> std::io::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut metadata = std::collections::BTreeMap::new();
+    let mut images = std::collections::BTreeMap::new();
+
+    for entry in read_dir(path)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue; // no sub-dirs
+        }
+        let (stem, extension) = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => (
+                stem.to_string_lossy().to_string(),
+                extension.to_string_lossy().to_lowercase(),
+            ),
+            _ => continue,
+        };
+        let is_image = IMAGE_EXTENSIONS.contains(&extension.as_str());
+        let json_stem = metadata_stem(&path).filter(|_| !is_image);
+        let is_ours = (json_stem.is_some() || is_image)
+            && !BOOKKEEPING_FILES
+                .iter()
+                .any(|file| path.file_name() == Some(file.as_ref()));
+        if !is_ours {
+            continue; // not ours
+        }
+
+        let content = read(&path)?;
+        if content.is_empty() {
+            report.empty_files.push(path);
+            continue;
+        }
+
+        if is_image {
+            let expected = if extension == "jpeg" {
+                "jpg"
+            } else {
+                &extension
+            };
+            if image_format(&content) != Some(expected) {
+                report.mismatched.push(path.clone());
+            }
+            images.insert(stem, path);
+        } else {
+            let json = decompress_metadata(content).map_err(|e| e.to_string());
+            match json
+                .and_then(|json| serde_json::from_slice::<Emoji>(&json).map_err(|e| e.to_string()))
+            {
+                Ok(emoji) => {
+                    metadata.insert(json_stem.unwrap_or(stem), emoji);
+                }
+                Err(e) => log::error!("Could not parse JSON {:?}: {}", path, e),
+            }
+        }
+    }
+
+    report.missing_images = metadata
+        .iter()
+        .filter(|(stem, emoji)| emoji.is_alias == 0 && !images.contains_key(*stem))
+        .map(|(_, emoji)| emoji.name.clone())
+        .collect();
+    report.orphan_images = images
+        .into_iter()
+        .filter(|(stem, _)| !metadata.contains_key(stem))
+        .map(|(_, path)| path)
+        .collect();
+    Ok(report)
+}
+
+pub(crate) fn verify(verify_opts: VerifyOptions) -> Result<Summary, Error> {
+    let path = &verify_opts.path;
+
+    let report =
+        verify_dir(path).map_err(|e| Error::Io(format!("Could not verify {:?}", path), e))?;
+
+    for name in &report.missing_images {
+        println!("missing image: {}", name);
+    }
+    for path in &report.orphan_images {
+        println!("orphan image: {}", path.display());
+    }
+    for path in &report.empty_files {
+        println!("empty file: {}", path.display());
+    }
+    for path in &report.mismatched {
+        println!("wrong format: {}", path.display());
+    }
+
+    if verify_opts.fix {
+        for path in report.empty_files.iter().chain(&report.orphan_images) {
+            log::debug!("Removing {:?}", path);
+            if let Err(e) = remove_file(path) {
+                log::error!("Could not remove {:?}: {}", path, e);
+            }
+        }
+    }
+
+    Ok(Summary::failed(report.len()))
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+    use slack_emoji::test_support::TestDir;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn formats() {
+        assert_eq!(image_format(PNG), Some("png"));
+        assert_eq!(image_format(b"GIF89a..."), Some("gif"));
+        assert_eq!(image_format(&[0xff, 0xd8, 0xff, 0xe0]), Some("jpg"));
+        assert_eq!(image_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_format(b"<html>"), None);
+    }
+
+    #[test]
+    fn report() {
+        let dir = TestDir::new("verify-test-dir");
+        std::fs::create_dir(dir.path).unwrap();
+        let write_json = |name: &str, is_alias: u8| {
+            let mut emoji = Emoji::new(name);
+            emoji.is_alias = is_alias;
+            std::fs::write(
+                dir.path.join(name).with_extension("json"),
+                serde_json::to_string(&emoji).unwrap(),
+            )
+            .unwrap();
+        };
+
+        write_json("complete", 0);
+        std::fs::write(dir.path.join("complete.png"), PNG).unwrap();
+        write_json("missing", 0);
+        write_json("alias", 1);
+        std::fs::write(dir.path.join("orphan.png"), PNG).unwrap();
+        write_json("empty", 0);
+        std::fs::write(dir.path.join("empty.gif"), b"").unwrap();
+        write_json("wrong", 0);
+        std::fs::write(dir.path.join("wrong.gif"), PNG).unwrap();
+        std::fs::write(dir.path.join("README.md"), b"not an emoji").unwrap();
+
+        let report = verify_dir(dir.path).expect("could not verify");
+        assert_eq!(report.missing_images, vec!["empty", "missing"]);
+        assert_eq!(report.orphan_images, vec![dir.path.join("orphan.png")]);
+        assert_eq!(report.empty_files, vec![dir.path.join("empty.gif")]);
+        assert_eq!(report.mismatched, vec![dir.path.join("wrong.gif")]);
+        assert_ne!(report.len(), 0);
+    }
+}
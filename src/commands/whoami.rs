@@ -0,0 +1,17 @@
+//! `whoami`
+
+use crate::*;
+
+pub(crate) fn whoami(
+    client: &RetryingClient,
+    whoami_opts: WhoamiOptions,
+) -> Result<Summary, Error> {
+    let slack = whoami_opts.slack;
+    let auth = auth_test(client, &slack.workspace, &slack.token)
+        .map_err(|e| Error::Api(format!("The token doesn't work for {}", slack.workspace), e))?;
+
+    println!("User:       {}", auth.user);
+    println!("Team:       {} ({})", auth.team, auth.url);
+    println!("Token type: {}", token_type(&slack.token));
+    Ok(Summary::done())
+}
@@ -0,0 +1,338 @@
+//! Image files on disk: finding them, telling their format and size, and resizing them
+
+use crate::*;
+use std::convert::TryInto;
+use std::fs::read_dir;
+use std::path::PathBuf;
+
+/// Reads all emoji from the per-emoji JSON files in a directory
+///
+/// Files that can't be parsed are reported and skipped.
+pub(crate) fn read_emoji_dir(
+    path: &std::path::Path,
+) -> std::io::Result<Box<dyn Iterator<Item = Emoji>>> {
+    Ok(Box::new(
+        read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file()) // no sub-dirs
+            .filter(|entry| !BOOKKEEPING_FILES.contains(&&*entry.file_name().to_string_lossy()))
+            // only JSON files, compressed or not
+            .filter_map(|entry| metadata_stem(&entry.path()).map(|stem| (entry.path(), stem)))
+            .filter_map(|(path, stem)| match read_decompressed(&path) {
+                Ok(bytes) => Some((stem, bytes)),
+                Err(e) => {
+                    log::error!("Could not read {:?}: {}", path, e);
+                    None
+                }
+            })
+            .map(|(stem, bytes)| (stem, serde_json::from_slice::<Emoji>(&bytes)))
+            .filter_map(|(stem, maybe_emoji)| match maybe_emoji {
+                Err(e) => {
+                    log::error!("Could not parse JSON: {:?}", e);
+                    None
+                }
+                Ok(mut emoji) => {
+                    // keep the images next to the JSON, even if written under another name
+                    emoji.file_name = Some(stem);
+                    Some(emoji)
+                }
+            }),
+    ))
+}
+
+pub(crate) const IMAGE_EXTENSIONS: [&str; 5] = ["png", "gif", "jpg", "jpeg", "webp"];
+
+/// Normalizes an emoji name the way Slack does when adding an emoji
+pub(crate) fn normalize_emoji_name(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Derives the emoji name from an image path, `None` if it's not an image
+pub(crate) fn emoji_name_from_image_path(path: &std::path::Path) -> Option<String> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    if !IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some(normalize_emoji_name(&path.file_stem()?.to_string_lossy()))
+}
+
+/// Detects the image format from the magic bytes at the start of a file
+///
+/// Returns the canonical file extension of the format.
+pub(crate) fn image_format(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("jpg")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// The largest emoji images Slack accepts in pixels on either side, next to `MAX_EMOJI_BYTES`
+const MAX_UPLOAD_PIXELS: u32 = 128;
+
+/// Why `--auto-resize` doesn't work in builds without the `resize` feature
+pub(crate) const NO_RESIZE: &str =
+    "This build can't resize images, install it with --features resize";
+
+pub(crate) fn require_resize(auto_resize: bool) -> Result<(), Error> {
+    if auto_resize && !cfg!(feature = "resize") {
+        return Err(Error::InvalidArguments(NO_RESIZE.into()));
+    }
+    Ok(())
+}
+
+/// Reads the width and height of a png, gif, jpg, or webp image from its header
+pub(crate) fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]) as u32);
+    let le16 = |i: usize| Some(u16::from_le_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]) as u32);
+    let le24 = |i: usize| Some(le16(i)? | (*bytes.get(i + 2)? as u32) << 16);
+    match image_format(bytes)? {
+        "png" => Some((be16(16)? << 16 | be16(18)?, be16(20)? << 16 | be16(22)?)),
+        "gif" => Some((le16(6)?, le16(8)?)),
+        "jpg" => {
+            // the size is in the start of frame segment, after any number of others
+            let mut i = 2;
+            loop {
+                if *bytes.get(i)? != 0xff {
+                    return None;
+                }
+                match *bytes.get(i + 1)? {
+                    0xff => i += 1, // padding
+                    0x01 | 0xd0..=0xd9 => i += 2,
+                    0xc4 | 0xc8 | 0xcc => i += 2 + be16(i + 2)? as usize,
+                    0xc0..=0xcf => return Some((be16(i + 7)?, be16(i + 5)?)),
+                    _ => i += 2 + be16(i + 2)? as usize,
+                }
+            }
+        }
+        "webp" => match bytes.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = le16(21)? | le16(23)? << 16;
+                Some(((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether an image has more than one frame, `None` if that can't be told
+///
+/// Finds multi-frame GIFs, APNGs, and animated WebPs.
+pub(crate) fn image_animated(bytes: &[u8]) -> Option<bool> {
+    let be32 = |i: usize| Some(u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?));
+    match image_format(bytes)? {
+        "gif" => gif_animated(bytes),
+        "png" => {
+            // APNGs announce their frames in an acTL chunk before the image data
+            let mut i = 8;
+            loop {
+                match bytes.get(i + 4..i + 8)? {
+                    b"acTL" => return Some(be32(i + 8)? > 1),
+                    b"IDAT" => return Some(false),
+                    _ => i += 12 + be32(i)? as usize,
+                }
+            }
+        }
+        "webp" => match bytes.get(12..16)? {
+            b"VP8X" => Some(bytes.get(20)? & 0x02 != 0),
+            _ => Some(false),
+        },
+        _ => Some(false),
+    }
+}
+
+/// Walks the blocks of a GIF until it finds a second frame or the end
+fn gif_animated(bytes: &[u8]) -> Option<bool> {
+    let color_table = |flags: u8| {
+        if flags & 0x80 != 0 {
+            3 << ((flags & 0x07) + 1)
+        } else {
+            0
+        }
+    };
+    let skip_sub_blocks = |mut i: usize| loop {
+        match *bytes.get(i)? as usize {
+            0 => return Some(i + 1),
+            length => i += 1 + length,
+        }
+    };
+    let mut i = 13 + color_table(*bytes.get(10)?);
+    let mut frames = 0;
+    loop {
+        match *bytes.get(i)? {
+            // an image descriptor with its color table, LZW code size, and data
+            0x2c => {
+                frames += 1;
+                if frames > 1 {
+                    return Some(true);
+                }
+                i = skip_sub_blocks(i + 11 + color_table(*bytes.get(i + 9)?))?;
+            }
+            // an extension with its label
+            0x21 => i = skip_sub_blocks(i + 2)?,
+            0x3b => return Some(false),
+            _ => return None,
+        }
+    }
+}
+
+/// Why Slack would reject an image, `None` if it fits its limits
+pub(crate) fn upload_limit_problem(image: &[u8]) -> Option<String> {
+    let mut problems = vec![];
+    if let Some((width, height)) = image_dimensions(image) {
+        if width > MAX_UPLOAD_PIXELS || height > MAX_UPLOAD_PIXELS {
+            problems.push(format!(
+                "{}×{} pixels, larger than Slack's {}×{}",
+                width, height, MAX_UPLOAD_PIXELS, MAX_UPLOAD_PIXELS
+            ));
+        }
+    }
+    if image.len() as u64 > MAX_EMOJI_BYTES {
+        problems.push(format!(
+            "{} bytes, more than Slack's {}",
+            image.len(),
+            MAX_EMOJI_BYTES
+        ));
+    }
+    if problems.is_empty() {
+        None
+    } else {
+        Some(format!("Image is {}", problems.join(" and ")))
+    }
+}
+
+/// Makes sure an image fits Slack's limits before uploading it
+///
+/// With `auto_resize`, oversized images are scaled down, and animated GIFs that are still too
+/// big lose all but their first frame. Otherwise they are rejected with the reason.
+pub(crate) fn fit_upload_limits(
+    name: &str,
+    image: Vec<u8>,
+    auto_resize: bool,
+    pb: &dyn Progress,
+) -> Result<Vec<u8>, String> {
+    let problem = match upload_limit_problem(&image) {
+        Some(problem) => problem,
+        None => return Ok(image),
+    };
+    if !auto_resize {
+        return Err(format!("{}, skipping it (see --auto-resize)", problem));
+    }
+    let mut resized = resize_image(&image, false)?;
+    if upload_limit_problem(&resized).is_some() && image_format(&image) == Some("gif") {
+        pb.println(&format!(
+            "{}: Still too big when scaled down, only keeping the first frame",
+            name
+        ));
+        resized = resize_image(&image, true)?;
+    }
+    match upload_limit_problem(&resized) {
+        Some(problem) => Err(format!("{} even after resizing, skipping it", problem)),
+        None => Ok(resized),
+    }
+}
+
+/// Scales an image down to `MAX_UPLOAD_PIXELS` via ImageMagick
+#[cfg(feature = "resize")]
+fn resize_image(image: &[u8], first_frame_only: bool) -> Result<Vec<u8>, String> {
+    let format = image_format(image).ok_or("Unknown image format, can't resize it")?;
+    let input = if first_frame_only { "-[0]" } else { "-" };
+    let size = format!("{0}x{0}>", MAX_UPLOAD_PIXELS);
+    let output = format!("{}:-", format);
+    convert(
+        image,
+        &[
+            input,
+            "-coalesce",
+            "-resize",
+            &size,
+            "-layers",
+            "Optimize",
+            &output,
+        ],
+    )
+}
+
+/// The ImageMagick commands that convert images, in the order they're tried
+///
+/// ImageMagick 7 installs `magick`, older versions only `convert`. On Windows, `convert` is the
+/// FAT to NTFS disk converter instead, so it's never run there.
+#[cfg(feature = "resize")]
+const IMAGEMAGICK_COMMANDS: &[&str] = if cfg!(windows) {
+    &["magick"]
+} else {
+    &["magick", "convert"]
+};
+
+/// Converts `input` with ImageMagick, given on STDIN, returning what it writes to STDOUT
+///
+/// Images go through the pipes instead of file names, which ImageMagick would parse for
+/// formats and frames.
+#[cfg(feature = "resize")]
+pub(crate) fn convert(input: &[u8], args: &[&str]) -> Result<Vec<u8>, String> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    let mut spawned = None;
+    for &program in IMAGEMAGICK_COMMANDS {
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        match child {
+            Ok(child) => {
+                spawned = Some((program, child));
+                break;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Could not run {}: {}", program, e)),
+        }
+    }
+    let (program, mut child) = spawned.ok_or_else(|| {
+        format!(
+            "ImageMagick isn't installed, found none of {}",
+            IMAGEMAGICK_COMMANDS.join(", ")
+        )
+    })?;
+    // ImageMagick reads all of its input before writing anything
+    let written = child.stdin.take().expect("stdin is piped").write_all(input);
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("{} failed: {}", program, e))?;
+    match (output.status.success(), written) {
+        (true, Ok(())) => Ok(output.stdout),
+        (true, Err(e)) => Err(format!("Could not send the input to {}: {}", program, e)),
+        (false, _) => Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+#[cfg(not(feature = "resize"))]
+fn resize_image(_image: &[u8], _first_frame_only: bool) -> Result<Vec<u8>, String> {
+    Err(NO_RESIZE.to_string())
+}
+
+/// Lists all images in a directory as `(emoji name, path)` pairs, sorted by name
+pub(crate) fn read_image_dir(path: &std::path::Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut images: Vec<(String, PathBuf)> = read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file()) // no sub-dirs
+        .filter_map(|path| emoji_name_from_image_path(&path).map(|name| (name, path)))
+        .collect();
+    images.sort();
+    Ok(images)
+}
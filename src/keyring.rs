@@ -0,0 +1,153 @@
+//! Tokens stored in the OS keyring, via `security` on macOS and `secret-tool` elsewhere
+
+use std::io::Write;
+
+/// The service tokens are stored under in the OS keyring, with the workspace as account
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "slack-emoji";
+
+/// Why `auth` can't work on systems without `security` or `secret-tool`, like Windows
+#[cfg(feature = "keyring")]
+pub(crate) const KEYRING_UNSUPPORTED: &str =
+    "the keyring is only supported on macOS and Linux, use --token-file instead";
+
+/// Runs a keyring tool, feeding it `input`, and returns its output if it succeeded
+///
+/// Secrets are only ever passed via STDIN so they don't show up in the process list.
+#[cfg(feature = "keyring")]
+fn run_keyring_tool(args: &[&str], input: &str) -> std::io::Result<Option<String>> {
+    use std::process::{Command, Stdio};
+    let mut child = Command::new(args[0])
+        .args(&args[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    Ok(
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|_| output.status.success()),
+    )
+}
+
+/// Quotes an argument for the command language of `security -i`
+#[cfg(all(feature = "keyring", target_os = "macos"))]
+fn security_quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The token stored for a workspace, or None if there is none or no keyring is available
+#[cfg(feature = "keyring")]
+pub(crate) fn keyring_token(workspace: &str) -> Result<Option<String>, String> {
+    if cfg!(not(unix)) {
+        // there's no secret-tool, only programs that happen to have the same name
+        return Ok(None);
+    }
+    #[cfg(target_os = "macos")]
+    let args = [
+        "security",
+        "find-generic-password",
+        "-s",
+        KEYRING_SERVICE,
+        "-a",
+        workspace,
+        "-w",
+    ];
+    #[cfg(not(target_os = "macos"))]
+    let args = [
+        "secret-tool",
+        "lookup",
+        "service",
+        KEYRING_SERVICE,
+        "workspace",
+        workspace,
+    ];
+    match run_keyring_tool(&args, "") {
+        Ok(token) => Ok(token.filter(|token| !token.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None), // no keyring tool
+        Err(e) => Err(format!("Could not read the token from the keyring: {}", e)),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub(crate) fn keyring_token(_workspace: &str) -> Result<Option<String>, String> {
+    Ok(None)
+}
+
+/// Stores the token for a workspace, replacing any stored before
+#[cfg(feature = "keyring")]
+pub(crate) fn store_keyring_token(workspace: &str, token: &str) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    let stored = run_keyring_tool(
+        &["security", "-i"],
+        &format!(
+            "add-generic-password -U -s {} -a {} -w {}\n",
+            security_quote(KEYRING_SERVICE),
+            security_quote(workspace),
+            security_quote(token)
+        ),
+    );
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let stored = run_keyring_tool(
+        &[
+            "secret-tool",
+            "store",
+            &format!("--label=Slack token for {}", workspace),
+            "service",
+            KEYRING_SERVICE,
+            "workspace",
+            workspace,
+        ],
+        token,
+    );
+    #[cfg(not(unix))]
+    let stored: std::io::Result<Option<String>> = Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        KEYRING_UNSUPPORTED,
+    ));
+
+    match stored {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err("the keyring refused to store the token".to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err("no keyring tool found, install libsecret's secret-tool or use --token-file".into())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Removes the token of a workspace from the keyring, returns whether there was one
+#[cfg(feature = "keyring")]
+pub(crate) fn delete_keyring_token(workspace: &str) -> Result<bool, String> {
+    if keyring_token(workspace)?.is_none() {
+        return Ok(false);
+    }
+    #[cfg(target_os = "macos")]
+    let args = [
+        "security",
+        "delete-generic-password",
+        "-s",
+        KEYRING_SERVICE,
+        "-a",
+        workspace,
+    ];
+    #[cfg(not(target_os = "macos"))]
+    let args = [
+        "secret-tool",
+        "clear",
+        "service",
+        KEYRING_SERVICE,
+        "workspace",
+        workspace,
+    ];
+    match run_keyring_tool(&args, "") {
+        Ok(Some(_)) => Ok(true),
+        Ok(None) => Err("the keyring refused to remove the token".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,512 @@
+//! The output formats of `list` and the JSON events of `--output-format json`
+
+use crate::*;
+
+/// What `list` writes emoji as
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ListFormat {
+    Json,
+    Ndjson,
+    Csv,
+    Emojipacks,
+    Markdown,
+    Sqlite,
+}
+
+impl std::str::FromStr for ListFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ListFormat::Json),
+            "ndjson" => Ok(ListFormat::Ndjson),
+            "csv" => Ok(ListFormat::Csv),
+            "emojipacks" => Ok(ListFormat::Emojipacks),
+            "markdown" => Ok(ListFormat::Markdown),
+            "sqlite" => Ok(ListFormat::Sqlite),
+            _ => Err(format!(
+                "unknown format {:?}, use 'json', 'ndjson', 'csv', 'emojipacks', 'markdown', or \
+                 'sqlite'",
+                s
+            )),
+        }
+    }
+}
+
+impl ListFormat {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ListFormat::Json => "json",
+            ListFormat::Ndjson => "ndjson",
+            ListFormat::Csv => "csv",
+            ListFormat::Emojipacks => "emojipacks",
+            ListFormat::Markdown => "markdown",
+            ListFormat::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// How `list --format markdown` splits its table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GroupBy {
+    Letter,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "letter" => Ok(GroupBy::Letter),
+            _ => Err(format!("unknown grouping {:?}, use 'letter'", s)),
+        }
+    }
+}
+
+impl GroupBy {
+    /// The group of an emoji, `None` for names that don't start with a letter or digit
+    pub(crate) fn group(self, e: &Emoji) -> Option<char> {
+        match self {
+            GroupBy::Letter => e
+                .name
+                .chars()
+                .next()
+                .filter(|c| c.is_alphanumeric())
+                .and_then(|c| c.to_lowercase().next()),
+        }
+    }
+}
+
+/// A column of `list --format csv`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CsvColumn {
+    Name,
+    Url,
+    Created,
+    UserDisplayName,
+    IsAlias,
+    AliasFor,
+    UserId,
+}
+
+impl std::str::FromStr for CsvColumn {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CsvColumn::ALL
+            .iter()
+            .copied()
+            .find(|column| column.name() == s.trim())
+            .ok_or_else(|| {
+                let names: Vec<&str> = CsvColumn::ALL.iter().map(|c| c.name()).collect();
+                format!("unknown column {:?}, use {}", s, names.join(", "))
+            })
+    }
+}
+
+impl CsvColumn {
+    const ALL: [CsvColumn; 7] = [
+        CsvColumn::Name,
+        CsvColumn::Url,
+        CsvColumn::Created,
+        CsvColumn::UserDisplayName,
+        CsvColumn::IsAlias,
+        CsvColumn::AliasFor,
+        CsvColumn::UserId,
+    ];
+
+    /// The name in `--columns` and the header
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Name => "name",
+            CsvColumn::Url => "url",
+            CsvColumn::Created => "created",
+            CsvColumn::UserDisplayName => "user_display_name",
+            CsvColumn::IsAlias => "is_alias",
+            CsvColumn::AliasFor => "alias_for",
+            CsvColumn::UserId => "user_id",
+        }
+    }
+
+    pub(crate) fn value(self, e: &Emoji) -> String {
+        match self {
+            CsvColumn::Name => e.name.clone(),
+            CsvColumn::Url => e.url.clone(),
+            CsvColumn::Created => e.created.iso8601(),
+            CsvColumn::UserDisplayName => e.user_display_name.clone(),
+            CsvColumn::IsAlias => (e.is_alias != 0).to_string(),
+            CsvColumn::AliasFor => e.alias_for.clone(),
+            CsvColumn::UserId => e.user_id.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// A value that `--format-string` can show with `{name}`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placeholder {
+    Name,
+    IsAlias,
+    AliasFor,
+    Url,
+    Created,
+    CreatedIso8601,
+    UserDisplayName,
+    AvatarHash,
+    UserId,
+    TeamId,
+    UserRealName,
+    UserEmail,
+    UnavailableFields,
+    ImageSha256,
+    ImageBytes,
+    Animated,
+    Width,
+    Height,
+    FileName,
+    UnknownFields,
+    /// How downloading the image went, only known to `download`
+    Status,
+    /// Where the image was downloaded to, only known to `download`
+    Path,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 22] = [
+        Placeholder::Name,
+        Placeholder::IsAlias,
+        Placeholder::AliasFor,
+        Placeholder::Url,
+        Placeholder::Created,
+        Placeholder::CreatedIso8601,
+        Placeholder::UserDisplayName,
+        Placeholder::AvatarHash,
+        Placeholder::UserId,
+        Placeholder::TeamId,
+        Placeholder::UserRealName,
+        Placeholder::UserEmail,
+        Placeholder::UnavailableFields,
+        Placeholder::ImageSha256,
+        Placeholder::ImageBytes,
+        Placeholder::Animated,
+        Placeholder::Width,
+        Placeholder::Height,
+        Placeholder::FileName,
+        Placeholder::UnknownFields,
+        Placeholder::Status,
+        Placeholder::Path,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Placeholder::Name => "name",
+            Placeholder::IsAlias => "is_alias",
+            Placeholder::AliasFor => "alias_for",
+            Placeholder::Url => "url",
+            Placeholder::Created => "created",
+            Placeholder::CreatedIso8601 => "created_iso8601",
+            Placeholder::UserDisplayName => "user_display_name",
+            Placeholder::AvatarHash => "avatar_hash",
+            Placeholder::UserId => "user_id",
+            Placeholder::TeamId => "team_id",
+            Placeholder::UserRealName => "user_real_name",
+            Placeholder::UserEmail => "user_email",
+            Placeholder::UnavailableFields => "unavailable_fields",
+            Placeholder::ImageSha256 => "image_sha256",
+            Placeholder::ImageBytes => "image_bytes",
+            Placeholder::Animated => "animated",
+            Placeholder::Width => "width",
+            Placeholder::Height => "height",
+            Placeholder::FileName => "file_name",
+            Placeholder::UnknownFields => "unknown_fields",
+            Placeholder::Status => "status",
+            Placeholder::Path => "path",
+        }
+    }
+
+    /// The value for an emoji, with `event` being its download, empty if there's none
+    fn value(self, e: &Emoji, event: Option<&Event>) -> String {
+        let (status, path) = match event {
+            Some(Event::Download { status, path, .. }) => (Some(*status), *path),
+            _ => (None, None),
+        };
+        match self {
+            Placeholder::Name => e.name.clone(),
+            Placeholder::IsAlias => (e.is_alias != 0).to_string(),
+            Placeholder::AliasFor => e.alias_for.clone(),
+            Placeholder::Url => e.url.clone(),
+            Placeholder::Created => e.created.seconds().to_string(),
+            Placeholder::CreatedIso8601 => e.created.iso8601(),
+            Placeholder::UserDisplayName => e.user_display_name.clone(),
+            Placeholder::AvatarHash => e.avatar_hash.clone(),
+            Placeholder::UserId => e.user_id.clone().unwrap_or_default(),
+            Placeholder::TeamId => e.team_id.clone().unwrap_or_default(),
+            Placeholder::UserRealName => e.user_real_name.clone().unwrap_or_default(),
+            Placeholder::UserEmail => e.user_email.clone().unwrap_or_default(),
+            Placeholder::UnavailableFields => e.unavailable_fields.join(","),
+            Placeholder::ImageSha256 => e.image_sha256.clone().unwrap_or_default(),
+            Placeholder::ImageBytes => e.image_bytes.map(|b| b.to_string()).unwrap_or_default(),
+            Placeholder::Animated => e.animated.map(|a| a.to_string()).unwrap_or_default(),
+            Placeholder::Width => e.width.map(|w| w.to_string()).unwrap_or_default(),
+            Placeholder::Height => e.height.map(|h| h.to_string()).unwrap_or_default(),
+            Placeholder::FileName => e.file_name.clone().unwrap_or_default(),
+            Placeholder::UnknownFields => serde_json::json!(e.unknown_fields).to_string(),
+            Placeholder::Status => match status {
+                Some(EventStatus::Ok) => "ok".into(),
+                Some(EventStatus::Skipped) => "skipped".into(),
+                Some(EventStatus::Failed) => "failed".into(),
+                None => String::new(),
+            },
+            Placeholder::Path => path
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A line to write per emoji, see `--format-string`
+///
+/// `{name}` and the other placeholders are replaced by values of the emoji, `{{` and `}}` are
+/// literal braces, and `\t`, `\n`, and `\\` are a tab, a line break, and a backslash.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Template(Vec<TemplatePart>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+impl std::str::FromStr for Template {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    text.push(c);
+                }
+                ('\\', Some('t')) => {
+                    chars.next();
+                    text.push('\t');
+                }
+                ('\\', Some('n')) => {
+                    chars.next();
+                    text.push('\n');
+                }
+                ('\\', Some('\\')) => {
+                    chars.next();
+                    text.push('\\');
+                }
+                ('{', _) => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("{{{} is missing its }}", name)),
+                        }
+                    }
+                    let placeholder = Placeholder::ALL
+                        .iter()
+                        .copied()
+                        .find(|p| p.name() == name.trim())
+                        .ok_or_else(|| {
+                            let names: Vec<String> = Placeholder::ALL
+                                .iter()
+                                .map(|p| format!("{{{}}}", p.name()))
+                                .collect();
+                            format!(
+                                "unknown placeholder {{{}}}, use {}, or {{{{ and }}}} for braces",
+                                name,
+                                names.join(", ")
+                            )
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Placeholder(placeholder));
+                }
+                ('}', _) => return Err("a single } needs to be written as }}".into()),
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(Template(parts))
+    }
+}
+
+impl Template {
+    /// The line for an emoji, with `event` being its download if there was one
+    pub(crate) fn render(&self, e: &Emoji, event: Option<&Event>) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Placeholder(placeholder) => placeholder.value(e, event),
+            })
+            .collect()
+    }
+
+    /// Whether this shows `{status}` or `{path}`, which only `download` knows
+    pub(crate) fn shows_download(&self) -> bool {
+        self.0.iter().any(|part| {
+            matches!(
+                part,
+                TemplatePart::Placeholder(Placeholder::Status | Placeholder::Path)
+            )
+        })
+    }
+}
+
+/// Joins fields into a line of CSV, quoting the ones with commas, quotes, or line breaks
+pub(crate) fn csv_line<S: AsRef<str>>(fields: impl Iterator<Item = S>) -> String {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    fields.join(",")
+}
+
+/// What `list` orders emoji by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SortKey {
+    Created,
+    Name,
+    User,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(SortKey::Created),
+            "name" => Ok(SortKey::Name),
+            "user" => Ok(SortKey::User),
+            _ => Err(format!(
+                "unknown sort key {:?}, use 'created', 'name', or 'user'",
+                s
+            )),
+        }
+    }
+}
+
+impl SortKey {
+    /// Sorts emoji by this key, with ties broken by name so the order is always the same
+    pub(crate) fn sort(self, emoji: &mut [Emoji], reverse: bool) {
+        let by_name = |a: &Emoji, b: &Emoji| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        };
+        match self {
+            SortKey::Created => emoji.sort_by(|a, b| a.created.cmp(&b.created).then(by_name(a, b))),
+            SortKey::Name => emoji.sort_by(by_name),
+            SortKey::User => emoji.sort_by(|a, b| {
+                a.user_display_name
+                    .cmp(&b.user_display_name)
+                    .then(by_name(a, b))
+            }),
+        }
+        if reverse {
+            emoji.reverse();
+        }
+    }
+}
+
+/// How commands print their results, `Text` for humans or `Json` for scripts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "unknown output format {:?}, use 'text' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Prints an event as a line of JSON, unless the output is text
+    pub(crate) fn emit(self, event: &Event) {
+        if self != OutputFormat::Json {
+            return;
+        }
+        match serde_json::to_string(event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => log::error!("Could not serialize {:?}: {}", event, e),
+        }
+    }
+
+    /// Progress is only shown to humans, it would get in the way of the events
+    pub(crate) fn progress(self, progress: ProgressMode) -> ProgressMode {
+        match self {
+            OutputFormat::Text => progress,
+            OutputFormat::Json => ProgressMode::None,
+        }
+    }
+}
+
+/// Something `list` or `download` did, see `--output-format json`
+///
+/// The field names are part of the interface, scripts rely on them.
+#[derive(serde::Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event<'a> {
+    /// Slack listed an emoji
+    Emoji { name: &'a str, url: &'a str },
+    /// The metadata of an emoji was written to a file of its own
+    Written {
+        name: &'a str,
+        path: &'a std::path::Path,
+    },
+    /// The image of an emoji was downloaded, already up to date, or couldn't be downloaded
+    Download {
+        name: &'a str,
+        status: EventStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<&'a std::path::Path>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+    /// Slack sent a field that isn't known, with the first value it had
+    UnknownField {
+        field: &'a str,
+        example: &'a serde_json::Value,
+    },
+    /// How the whole command went, `total` is the sum of the other counts
+    Summary {
+        command: &'a str,
+        total: usize,
+        ok: usize,
+        skipped: usize,
+        failed: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bytes: Option<u64>,
+    },
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EventStatus {
+    Ok,
+    Skipped,
+    Failed,
+}
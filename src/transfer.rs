@@ -0,0 +1,152 @@
+//! Progress bars and rate limits shared by the commands that transfer images
+
+use crate::*;
+use std::time::{Duration, Instant};
+
+/// The progress bar template of commands that go through emoji one by one
+pub(crate) const PROGRESS_TEMPLATE: &str = "{wide_bar} {pos}/{len:.dim} [{eta} left] {msg:<25!}";
+
+/// A progress bar that also counts the bytes transferred, shown with their rate in its prefix
+pub(crate) struct TransferProgress<'a> {
+    pub(crate) pb: &'a dyn Progress,
+    bytes: std::sync::atomic::AtomicU64,
+    /// How many bytes are expected in total, if known
+    expected: Option<u64>,
+    started: Instant,
+    /// How many images needed more than one attempt
+    retried: std::sync::atomic::AtomicUsize,
+}
+
+impl<'a> std::ops::Deref for TransferProgress<'a> {
+    type Target = dyn Progress + 'a;
+    fn deref(&self) -> &(dyn Progress + 'a) {
+        self.pb
+    }
+}
+
+impl<'a> TransferProgress<'a> {
+    pub fn new(pb: &'a dyn Progress, expected: Option<u64>) -> TransferProgress<'a> {
+        TransferProgress {
+            pb,
+            bytes: Default::default(),
+            expected,
+            started: Instant::now(),
+            retried: Default::default(),
+        }
+    }
+
+    pub fn add(&self, bytes: u64) {
+        let total = self
+            .bytes
+            .fetch_add(bytes, std::sync::atomic::Ordering::Relaxed)
+            + bytes;
+        self.show(total);
+    }
+
+    /// Takes back the bytes of an attempt that failed, so a retry doesn't count them twice
+    pub fn discard(&self, bytes: u64) {
+        let total = self
+            .bytes
+            .fetch_sub(bytes, std::sync::atomic::Ordering::Relaxed)
+            - bytes;
+        self.show(total);
+    }
+
+    fn show(&self, total: u64) {
+        let per_second = total as f64 / self.started.elapsed().as_secs_f64().max(0.001);
+        let expected = match self.expected {
+            Some(expected) => format!("/{}", indicatif::HumanBytes(expected)),
+            None => String::new(),
+        };
+        self.pb.set_prefix(&format!(
+            "{}{} ({}/s)",
+            indicatif::HumanBytes(total),
+            expected,
+            indicatif::HumanBytes(per_second as u64)
+        ));
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn retried(&self) -> usize {
+        self.retried.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// Fetches an image like `fetch_image`, trying again up to `retries` times after errors that
+/// might go away, like timeouts or connection resets
+///
+/// Server errors and rate limits were already retried by the `RetryingClient`.
+pub(crate) fn fetch_image_retrying(
+    client: &RetryingClient,
+    url: &str,
+    validators: Option<&CacheValidators>,
+    retries: u32,
+    progress: &TransferProgress,
+) -> reqwest::Result<Option<FetchedImage>> {
+    let mut attempt = 0;
+    loop {
+        let received = std::sync::atomic::AtomicU64::new(0);
+        let result = fetch_image(client, url, validators, &|bytes| {
+            received.fetch_add(bytes, std::sync::atomic::Ordering::Relaxed);
+            progress.add(bytes)
+        });
+        if result.is_err() {
+            progress.discard(received.into_inner());
+        }
+        match result {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                let delay = Duration::from_millis(500 << attempt);
+                progress.println(&format!("{}, trying again in {:?}", e, delay));
+                std::thread::sleep(delay);
+            }
+            result => {
+                if attempt > 0 {
+                    progress
+                        .retried
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                return result;
+            }
+        }
+    }
+}
+
+/// Spaces out requests to stay below a maximum request rate, even across threads
+///
+/// This is a token bucket holding at most one token, so requests are spread out evenly instead
+/// of coming in bursts. A rate of 0 means unlimited.
+pub(crate) struct RateLimiter {
+    per_second: f64,
+    /// The tokens left and when they were counted, negative while requests are waiting
+    bucket: std::sync::Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> RateLimiter {
+        RateLimiter {
+            per_second,
+            bucket: std::sync::Mutex::new((1.0, Instant::now())),
+        }
+    }
+
+    /// Blocks until the next request may be sent
+    pub fn wait(&self) {
+        if self.per_second <= 0.0 {
+            return;
+        }
+        let delay = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let (tokens, counted) = *bucket;
+            let now = Instant::now();
+            let refilled = now.duration_since(counted).as_secs_f64() * self.per_second;
+            let tokens = (tokens + refilled).min(1.0) - 1.0;
+            *bucket = (tokens, now);
+            Duration::from_secs_f64(tokens.min(0.0).abs() / self.per_second)
+        };
+        std::thread::sleep(delay);
+    }
+}